@@ -0,0 +1,193 @@
+// animation.rs
+use crate::metadata::riff_chunks;
+use image::codecs::gif::GifDecoder;
+use image::error::{DecodingError, ImageFormatHint};
+use image::{AnimationDecoder, ImageError, ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+/// Delays at or below this many milliseconds play at `DEFAULT_DELAY_MS` in browsers, so they are stored that way.
+const MIN_DELAY_MS: u32 = 10;
+const DEFAULT_DELAY_MS: u32 = 100;
+/// Shortest delay a retimed frame gets, keeping it clear of `MIN_DELAY_MS`.
+const MIN_RETIMED_DELAY_MS: u32 = 20;
+
+/// One fully composited canvas and how long it stays on screen.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub image: RgbaImage,
+    pub delay_ms: u32,
+}
+
+/// Decoded frames of an animated source, every one the size of the canvas.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// Times the animation plays, 0 for forever.
+    pub loop_count: u16,
+}
+
+impl Animation {
+    /// Decodes every frame of a GIF, applying each frame's disposal so frames come out as whole canvases.
+    pub fn decode_gif(data: &[u8]) -> Result<Self, ImageError> {
+        let frames = GifDecoder::new(Cursor::new(data))?.into_frames().collect_frames()?;
+        let frames = frames.into_iter().map(|frame| {
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let delay_ms = numerator / denominator.max(1);
+            Frame { image: frame.into_buffer(), delay_ms: if delay_ms <= MIN_DELAY_MS { DEFAULT_DELAY_MS } else { delay_ms } }
+        }).collect();
+        Ok(Self { frames, loop_count: gif_loop_count(data) })
+    }
+
+    /// Decodes every frame of an animated WebP through libwebp, which composites frames onto the canvas.
+    pub fn decode_webp(data: &[u8]) -> Result<Self, ImageError> {
+        let decoded = ::webp::AnimDecoder::new(data).decode().map_err(|e| {
+            ImageError::Decoding(DecodingError::new(ImageFormatHint::Exact(ImageFormat::WebP), e))
+        })?;
+        // Timestamps mark where each frame ends
+        let mut previous_end = 0;
+        let frames = decoded.into_iter().filter_map(|frame| {
+            let delay_ms = (frame.get_time_ms() - previous_end).max(0) as u32;
+            previous_end = frame.get_time_ms();
+            let image = RgbaImage::from_raw(frame.width(), frame.height(), frame.get_image().to_vec())?;
+            Some(Frame { image, delay_ms })
+        }).collect();
+        Ok(Self { frames, loop_count: decoded.loop_count.min(u16::MAX as u32) as u16 })
+    }
+
+    /// Whether `data` is a WebP file with the animation flag set.
+    pub fn is_animated_webp(data: &[u8]) -> bool {
+        data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP")
+            // The VP8X flags byte has the animation bit at 0x02
+            && riff_chunks(data).next().is_some_and(|(kind, payload)| kind == b"VP8X" && payload.first().is_some_and(|flags| flags & 0x02 != 0))
+    }
+
+    /// Plays `speed` times as fast (0.5 is half speed); `loop_count` replaces the source's when set.
+    pub fn retimed(mut self, speed: f32, loop_count: Option<u16>) -> Self {
+        if speed > 0.0 && speed != 1.0 {
+            for frame in &mut self.frames {
+                frame.delay_ms = ((frame.delay_ms as f32 / speed).round() as u32).max(MIN_RETIMED_DELAY_MS);
+            }
+        }
+        if let Some(loop_count) = loop_count {
+            self.loop_count = loop_count;
+        }
+        self
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames.first().map_or((0, 0), |frame| frame.image.dimensions())
+    }
+
+    /// Length of one play-through in milliseconds.
+    pub fn duration_ms(&self) -> u64 {
+        self.frames.iter().map(|frame| frame.delay_ms as u64).sum()
+    }
+
+    /// Frame images with consecutive repeats folded into one, as encoders store them.
+    pub fn distinct_frames(&self) -> Vec<&RgbaImage> {
+        let mut images: Vec<&RgbaImage> = self.frames.iter().map(|frame| &frame.image).collect();
+        images.dedup();
+        images
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.frames.iter().any(|frame| frame.image.pixels().any(|pixel| pixel.0[3] < 255))
+    }
+}
+
+/// Play count from the NETSCAPE2.0 extension, which stores repeats after the first play (0 for forever).
+/// GIFs without it play once.
+fn gif_loop_count(data: &[u8]) -> u16 {
+    let Some(at) = data.windows(11).position(|window| window == b"NETSCAPE2.0") else { return 1 };
+    match data.get(at + 11..at + 15) {
+        Some(&[3, 1, low, high]) => match u16::from_le_bytes([low, high]) {
+            0 => 0,
+            repeats => repeats.saturating_add(1),
+        },
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoders::webp::WebpEncoder;
+    use crate::encoders::{EncodingMode, ImageEncoder};
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, Rgba};
+
+    /// Four 8x6 frames with the given delays, each a different colour.
+    fn sample_gif(repeat: Option<Repeat>, delays_ms: &[u32]) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            if let Some(repeat) = repeat {
+                encoder.set_repeat(repeat).unwrap();
+            }
+            let frames = delays_ms.iter().enumerate().map(|(index, &delay_ms)| {
+                let image = RgbaImage::from_pixel(8, 6, Rgba([index as u8 * 60, 0, 255 - index as u8 * 60, 255]));
+                image::Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+        data
+    }
+
+    fn delays(animation: &Animation) -> Vec<u32> {
+        animation.frames.iter().map(|frame| frame.delay_ms).collect()
+    }
+
+    #[test]
+    fn decodes_gif_frames_and_delays() {
+        let animation = Animation::decode_gif(&sample_gif(None, &[100, 200, 10, 300])).unwrap();
+        assert_eq!(animation.frames.len(), 4);
+        assert_eq!(animation.dimensions(), (8, 6));
+        // Delays browsers clamp are stored at the delay they play at
+        assert_eq!(delays(&animation), vec![100, 200, DEFAULT_DELAY_MS, 300]);
+        assert_eq!(animation.duration_ms(), 700);
+        assert!(!animation.has_alpha());
+    }
+
+    #[test]
+    fn maps_netscape_repeats_to_play_counts() {
+        let loops = |repeat| gif_loop_count(&sample_gif(repeat, &[100, 100]));
+        assert_eq!(loops(Some(Repeat::Infinite)), 0);
+        // NETSCAPE counts repeats after the first play
+        assert_eq!(loops(Some(Repeat::Finite(2))), 3);
+        assert_eq!(loops(None), 1);
+    }
+
+    #[test]
+    fn retimes_frames_and_overrides_loops() {
+        let animation = Animation::decode_gif(&sample_gif(Some(Repeat::Finite(2)), &[100, 200, 30])).unwrap();
+        let faster = animation.clone().retimed(2.0, None);
+        // Retimed delays never drop into the range browsers clamp
+        assert_eq!(delays(&faster), vec![50, 100, MIN_RETIMED_DELAY_MS]);
+        assert_eq!(faster.loop_count, 3);
+        let slower = animation.retimed(0.5, Some(0));
+        assert_eq!(delays(&slower), vec![200, 400, 60]);
+        assert_eq!(slower.loop_count, 0);
+    }
+
+    #[test]
+    fn round_trips_a_retimed_gif_through_animated_webp() {
+        let animation = Animation::decode_gif(&sample_gif(Some(Repeat::Finite(2)), &[100, 200, 50, 300])).unwrap().retimed(2.0, None);
+        for mode in [EncodingMode::Lossy, EncodingMode::Lossless] {
+            let (data, _) = WebpEncoder::new().mode(mode).encode_animation(&animation).unwrap();
+            assert!(Animation::is_animated_webp(&data));
+            let decoded = Animation::decode_webp(&data).unwrap();
+            assert_eq!(decoded.frames.len(), 4);
+            assert_eq!(decoded.dimensions(), (8, 6));
+            assert_eq!(delays(&decoded), vec![50, 100, 25, 150]);
+            assert_eq!(decoded.loop_count, 3);
+        }
+    }
+
+    #[test]
+    fn still_webp_is_not_animated() {
+        let image = image::DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255])));
+        let data = WebpEncoder::new().encode(&image).unwrap();
+        assert!(!Animation::is_animated_webp(&data));
+        assert!(!Animation::is_animated_webp(&sample_gif(None, &[100, 100])));
+    }
+}
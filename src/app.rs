@@ -1,6 +1,5 @@
 // app.rs
 pub mod gui;
-pub mod image_processing;
 pub mod file_dialogs;
 
 use eframe::egui;
@@ -10,41 +9,70 @@ use std::sync::Arc;
 use parking_lot::Mutex;
 use std::time::Instant;
 use std::sync::mpsc::Receiver;
-
+use jpg_to_webp_coder::ConversionOptions;
+pub use jpg_to_webp_coder::{
+    builtin_encoders, AlphaHandling, ColorManagement, ConversionUpdate, ImageDetail, ImageEncoder, MetadataPolicy, QualityMetric, ResizeFilter, ResizeMode,
+    SymlinkPolicy,
+};
 
 pub struct App {
     // Application state
     pub input_files: Vec<PathBuf>,
+    /// Folder the selection came from, whose structure the outputs mirror.
+    pub input_root: Option<PathBuf>,
     pub output_directory: Option<PathBuf>,
+    /// Comma-separated glob patterns for "Select Folder", as typed.
+    pub folder_include: String,
+    pub folder_exclude: String,
+    pub include_hidden: bool,
+    pub symlinks: SymlinkPolicy,
+    /// File size bounds for "Select Folder" in KB, 0 for none.
+    pub min_size_kb: u64,
+    pub max_size_kb: u64,
+    pub auto_orient: bool,
     pub resize_enabled: bool,
+    pub resize_mode: ResizeMode,
     pub width: u32,
     pub height: u32,
-    pub compression_quality: f32,
+    pub max_edge: u32,
+    pub scale_percent: f32,
+    pub never_upscale: bool,
+    pub resize_filter: ResizeFilter,
+    pub linear_light: bool,
+    pub premultiplied_alpha: bool,
+    pub alpha: AlphaHandling,
+    pub background: [u8; 3],
     pub output_filename: String,
-    pub quality_enabled: bool,
     pub rename_enabled: bool,
+    /// Registered encoder backends, each holding its own settings.
+    pub encoders: Vec<Box<dyn ImageEncoder>>,
+    /// Names of the encoders to write with, in registration order.
+    pub selected_encoders: Vec<String>,
+    pub compute_metrics: bool,
+    pub metadata_policy: MetadataPolicy,
+    pub color_management: ColorManagement,
+    /// Playback speed of animated outputs relative to the source.
+    pub animation_speed: f32,
+    /// Whether `loop_count` replaces the source's loop count.
+    pub loop_override: bool,
+    pub loop_count: u16,
+    pub srcset_enabled: bool,
+    /// Comma-separated srcset widths as typed in the settings group.
+    pub srcset_widths: String,
     pub conversion_progress: Arc<Mutex<ConversionProgress>>,
     pub log_messages: Arc<Mutex<Vec<String>>>,
+    #[allow(dead_code)]
     pub selected_image: Option<PathBuf>,
     pub conversion_start_time: Option<Instant>,
     pub original_size: Option<u64>,
     pub compressed_size: Option<u64>,
     pub original_sizes: Arc<Mutex<Vec<u64>>>,
     pub compressed_sizes: Arc<Mutex<Vec<u64>>>,
-    pub original_width: u32,
-    pub original_height: u32,
     pub image_details: Arc<Mutex<Vec<ImageDetail>>>,
     pub currently_processing: Arc<Mutex<Option<usize>>>,
     pub conversion_receiver: Option<Receiver<ConversionUpdate>>,
-}
-
-#[derive(Clone)]
-pub enum ConversionUpdate {
-    Progress(usize, usize),  // (completed, total)
-    ImageProcessed(usize, Option<u64>, Option<f32>),  // (index, compressed_size, compression_rate)
-    Completed,
-    StatusUpdate(usize, String, Option<String>),  // (index, status, error_message)
-    ResultsUpdate(f64, f64),  // (total_original, total_compressed)
+    pub sort_column: Option<gui::SortColumn>,
+    pub sort_ascending: bool,
 }
 
 pub struct ConversionProgress {
@@ -53,28 +81,45 @@ pub struct ConversionProgress {
     pub status: String,
 }
 
-#[derive(Clone, Debug)]
-pub struct ImageDetail {
-    pub name: String,
-    pub original_size: u64,
-    pub compressed_size: Option<u64>,
-    pub compression_rate: Option<f32>,
-    pub status: String,
-    pub error_message: Option<String>,
-}
-
 impl Default for App {
     fn default() -> Self {
+        let defaults = ConversionOptions::default();
         Self {
             input_files: Vec::new(),
+            input_root: None,
             output_directory: None,
-            resize_enabled: false,
-            width: 800,
-            height: 600,
-            compression_quality: 80.0,
-            output_filename: String::from("output"),
-            quality_enabled: false,
-            rename_enabled: false,
+            folder_include: String::new(),
+            folder_exclude: String::new(),
+            include_hidden: false,
+            symlinks: SymlinkPolicy::Skip,
+            min_size_kb: 0,
+            max_size_kb: 0,
+            auto_orient: defaults.auto_orient,
+            resize_enabled: defaults.resize_enabled,
+            resize_mode: defaults.resize_mode,
+            width: defaults.width,
+            height: defaults.height,
+            max_edge: defaults.max_edge,
+            scale_percent: defaults.scale_percent,
+            never_upscale: defaults.never_upscale,
+            resize_filter: defaults.resize_filter,
+            linear_light: defaults.linear_light,
+            premultiplied_alpha: defaults.premultiplied_alpha,
+            alpha: defaults.alpha,
+            background: defaults.background,
+            output_filename: defaults.output_filename,
+            rename_enabled: defaults.rename_enabled,
+            encoders: builtin_encoders(),
+            selected_encoders: defaults.encoders,
+            compute_metrics: defaults.compute_metrics,
+            metadata_policy: defaults.metadata,
+            color_management: defaults.color_management,
+            animation_speed: defaults.animation_speed,
+            loop_override: defaults.loop_count.is_some(),
+            loop_count: defaults.loop_count.unwrap_or(0),
+            srcset_enabled: !defaults.srcset_widths.is_empty(),
+            // The library writes no variants by default; this is only the suggestion shown once enabled
+            srcset_widths: String::from("320, 640, 1280, 1920"),
             conversion_progress: Arc::new(Mutex::new(ConversionProgress {
                 total: 0,
                 completed: 0,
@@ -87,11 +132,11 @@ impl Default for App {
             compressed_size: None,
             original_sizes: Arc::new(Mutex::new(Vec::new())),
             compressed_sizes: Arc::new(Mutex::new(Vec::new())),
-            original_width: 0,
-            original_height: 0,
             image_details: Arc::new(Mutex::new(Vec::new())),
             currently_processing: Arc::new(Mutex::new(None)),
             conversion_receiver: None,
+            sort_column: None,
+            sort_ascending: true,
         }
     }
 }
@@ -108,6 +153,7 @@ impl EframeApp for App {
                         let mut progress = self.conversion_progress.lock();
                         progress.completed = completed;
                         progress.total = total;
+                        progress.status = format!("Converting image {} of {}", completed, total);
                         drop(progress); // Release the lock as soon as possible
                         needs_redraw = true;
                     }
@@ -116,6 +162,10 @@ impl EframeApp for App {
                         if let Some(detail) = image_details.get_mut(index) {
                             detail.compressed_size = compressed_size;
                             detail.compression_rate = compression_rate;
+                            if let Some(size) = compressed_size {
+                                self.original_sizes.lock().push(detail.original_size);
+                                self.compressed_sizes.lock().push(size);
+                            }
                         }
                         drop(image_details); // Release the lock as soon as possible
                         needs_redraw = true;
@@ -129,12 +179,149 @@ impl EframeApp for App {
                         drop(image_details); // Release the lock as soon as possible
                         needs_redraw = true;
                     }
+                    ConversionUpdate::EncodingUsed(index, mode) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.encoding_mode = Some(mode);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::AutoDecision(index, mode, reason) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.encoding_mode = Some(mode);
+                            detail.mode_reason = Some(reason);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::QualityUsed(index, quality) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.quality = Some(quality);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::MetricMeasured(index, metric, value) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.measured_metric = Some((metric, value));
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::FidelityMeasured(index, metrics) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.fidelity = Some(metrics);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::FormatProcessed(index, output) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.outputs.retain(|existing| (&existing.format, existing.width) != (&output.format, output.width));
+                            detail.outputs.push(output);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::SrcsetGenerated(index, format, srcset) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.srcsets.retain(|(existing, _)| *existing != format);
+                            detail.srcsets.push((format, srcset));
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::Dimensions(index, original, resized) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.original_dimensions = Some(original);
+                            detail.output_dimensions = Some(resized);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::FilterUsed(index, filter, linear) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.resize_filter = Some((filter, linear));
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::Oriented(index, orientation) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.orientation = Some(orientation);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::MetadataScrubbed(index, scrubbed) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.scrubbed = scrubbed;
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::ColorConverted(index, conversion) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.color_conversion = Some(conversion);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::SourceFormat(index, format) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.source_format = Some(format);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::Animated(index, frame_count, duration_ms) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.frame_count = Some(frame_count);
+                            detail.duration_ms = Some(duration_ms);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::PixelsConverted(index, steps) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.pixel_conversion = steps;
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
+                    ConversionUpdate::Cropped(index, crop) => {
+                        let mut image_details = self.image_details.lock();
+                        if let Some(detail) = image_details.get_mut(index) {
+                            detail.crop = Some(crop);
+                        }
+                        drop(image_details); // Release the lock as soon as possible
+                        needs_redraw = true;
+                    }
                     ConversionUpdate::ResultsUpdate(total_original, total_compressed) => {
                         self.original_size = Some(total_original as u64);
                         self.compressed_size = Some(total_compressed as u64);
                         needs_redraw = true;
                     }
                     ConversionUpdate::Completed => {
+                        self.conversion_progress.lock().status = "Conversion complete!".to_string();
+                        if let Some(start_time) = self.conversion_start_time.take() {
+                            self.log_messages.lock().push(format!("[{}] Conversion finished in {:?}", chrono::Local::now().format("%H:%M:%S"), start_time.elapsed()));
+                        }
                         completed = true;
                         needs_redraw = true;
                     }
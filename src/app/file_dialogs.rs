@@ -1,13 +1,18 @@
 // file_dialogs.rs
+use jpg_to_webp_coder::image_processing::INPUT_EXTENSIONS;
 use rfd::FileDialog;
 use std::path::PathBuf;
 
 pub fn select_images() -> Option<Vec<PathBuf>> {
     FileDialog::new()
-        .add_filter("Image", &["jpg", "jpeg", "png"])
+        .add_filter("Image", INPUT_EXTENSIONS)
         .pick_files()
 }
 
+pub fn select_folder() -> Option<PathBuf> {
+    FileDialog::new().pick_folder()
+}
+
 pub fn select_output_directory() -> Option<PathBuf> {
     FileDialog::new().pick_folder()
 }
@@ -1,11 +1,30 @@
 use std::sync::mpsc::channel;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use crate::app::App;
 use crate::app::file_dialogs;
-use crate::app::image_processing;
+use crate::app::AlphaHandling;
+use crate::app::ColorManagement;
 use crate::app::ImageDetail;
+use crate::app::ImageEncoder;
+use crate::app::MetadataPolicy;
+use crate::app::QualityMetric;
+use crate::app::ResizeFilter;
+use crate::app::ResizeMode;
+use crate::app::SymlinkPolicy;
+use jpg_to_webp_coder::image_processing::{is_supported_input, source_format_name};
+use jpg_to_webp_coder::{ConversionJob, ConversionOptions, Converter, FolderScan, EncoderOption, FormatOutput, OptionKind, OptionValue};
 use egui::{Color32, Frame, ProgressBar, Rounding, Slider, Stroke, RichText};
 
 pub fn render(app: &mut App, ctx: &egui::Context) {
+    let dropped: Vec<PathBuf> = ctx.input().raw.dropped_files.iter()
+        .filter_map(|file| file.path.clone())
+        .filter(|path| path.is_file() && is_supported_input(path))
+        .collect();
+    if !dropped.is_empty() {
+        select_images(app, dropped, None);
+    }
+
     let frame = Frame {
         fill: Color32::from_rgb(30, 30, 40),
         rounding: Rounding::same(10.0),
@@ -23,22 +42,34 @@ pub fn render(app: &mut App, ctx: &egui::Context) {
                 let button_width = 200.0;
                 if ui.add_sized([button_width, 30.0], egui::Button::new("Select Images")).clicked() {
                     if let Some(files) = file_dialogs::select_images() {
-                        app.input_files = files.clone();
-                        let image_details: Vec<ImageDetail> = files.iter().map(|path| {
-                            let metadata = std::fs::metadata(path).unwrap();
-                            ImageDetail {
-                                name: path.file_name().unwrap().to_string_lossy().into_owned(),
-                                original_size: metadata.len(),
-                                compressed_size: None,
-                                compression_rate: None,
-                                status: "Load successful".to_string(),
-                                error_message: None,
-                            }
-                        }).collect();
-                        *app.image_details.lock() = image_details;
-                        app.log_messages.lock().push(format!("[{}] Images selected successfully.", chrono::Local::now().format("%H:%M:%S")));
+                        select_images(app, files, None);
+                    }
+                }
+                ui.add_space(5.0);
+                if ui.add_sized([button_width, 30.0], egui::Button::new("Select Folder")).clicked() {
+                    if let Some(folder) = file_dialogs::select_folder() {
+                        select_folder(app, &folder);
                     }
                 }
+                egui::CollapsingHeader::new("Folder Filters").show(ui, |ui| {
+                    ui.set_width(button_width);
+                    ui.label("Include (e.g. *.png, icons/**/*)");
+                    ui.text_edit_singleline(&mut app.folder_include);
+                    ui.label("Exclude (e.g. drafts, *-old.*)");
+                    ui.text_edit_singleline(&mut app.folder_exclude);
+                    ui.checkbox(&mut app.include_hidden, "Include Hidden Files");
+                    egui::ComboBox::from_label("Symlinks")
+                        .selected_text(app.symlinks.to_string())
+                        .show_ui(ui, |ui| {
+                            for symlinks in SymlinkPolicy::ALL {
+                                ui.selectable_value(&mut app.symlinks, symlinks, symlinks.to_string());
+                            }
+                        });
+                    ui.add(egui::DragValue::new(&mut app.min_size_kb).suffix(" KB min"))
+                        .on_hover_text("0 for no minimum");
+                    ui.add(egui::DragValue::new(&mut app.max_size_kb).suffix(" KB max"))
+                        .on_hover_text("0 for no maximum");
+                });
                 ui.add_space(5.0);
                 if ui.add_sized([button_width, 30.0], egui::Button::new("Select Output Directory")).clicked() {
                     if let Some(dir) = file_dialogs::select_output_directory() {
@@ -66,11 +97,123 @@ pub fn render(app: &mut App, ctx: &egui::Context) {
                 ui.group(|ui| {
                     ui.set_width(button_width);
                     ui.label(RichText::new("Conversion Settings").size(16.0).color(Color32::from_rgb(100, 200, 250)));
-                    ui.add(Slider::new(&mut app.compression_quality, 1.0..=100.0).text("Quality"));
+                    ui.label("Formats");
+                    ui.horizontal_wrapped(|ui| {
+                        for encoder in &app.encoders {
+                            let name = encoder.name();
+                            let mut checked = app.selected_encoders.iter().any(|selected| selected == name);
+                            if ui.checkbox(&mut checked, name).changed() {
+                                if checked {
+                                    app.selected_encoders.push(name.to_string());
+                                } else {
+                                    app.selected_encoders.retain(|selected| selected != name);
+                                }
+                            }
+                        }
+                    });
+                    // Keep registration order so the first format (shown on the main row) is stable
+                    let encoders = &app.encoders;
+                    app.selected_encoders.sort_by_key(|name| encoders.iter().position(|encoder| encoder.name() == name));
+                    for encoder in app.encoders.iter_mut().filter(|encoder| app.selected_encoders.iter().any(|name| name == encoder.name())) {
+                        egui::CollapsingHeader::new(format!("{} Settings", encoder.name()))
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                encoder_settings_ui(ui, encoder.as_mut(), false);
+                                if encoder.options_schema().iter().any(|option| option.advanced) {
+                                    ui.collapsing("Advanced", |ui| {
+                                        encoder_settings_ui(ui, encoder.as_mut(), true);
+                                        if ui.button("Reset to defaults").clicked() {
+                                            encoder.reset_options();
+                                        }
+                                    });
+                                }
+                            });
+                    }
+                    ui.checkbox(&mut app.compute_metrics, "Compute Fidelity Metrics")
+                        .on_hover_text("Decode each output and report PSNR, SSIM and MS-SSIM (slower)");
+                    egui::ComboBox::from_label("Metadata")
+                        .selected_text(app.metadata_policy.to_string())
+                        .show_ui(ui, |ui| {
+                            for policy in MetadataPolicy::ALL {
+                                ui.selectable_value(&mut app.metadata_policy, policy, policy.to_string());
+                            }
+                        })
+                        .response
+                        .on_hover_text("EXIF, XMP and ICC profile carried into WebP and JPEG outputs; AVIF outputs drop them");
+                    egui::ComboBox::from_label("Color Profile")
+                        .selected_text(app.color_management.to_string())
+                        .show_ui(ui, |ui| {
+                            for color_management in ColorManagement::ALL {
+                                ui.selectable_value(&mut app.color_management, color_management, color_management.to_string());
+                            }
+                        })
+                        .response
+                        .on_hover_text("Wide-gamut images (Display P3, Adobe RGB) look dull unless converted or tagged");
+                    ui.checkbox(&mut app.auto_orient, "Apply EXIF Orientation")
+                        .on_hover_text("Rotate and flip photos upright per their EXIF Orientation tag");
                     ui.checkbox(&mut app.resize_enabled, "Enable Resizing");
+                    egui::ComboBox::from_label("Resize Mode")
+                        .selected_text(app.resize_mode.to_string())
+                        .show_ui(ui, |ui| {
+                            for mode in ResizeMode::ALL {
+                                ui.selectable_value(&mut app.resize_mode, mode, mode.to_string());
+                            }
+                        });
+                    ui.horizontal(|ui| match app.resize_mode {
+                        ResizeMode::Exact | ResizeMode::Fit | ResizeMode::Fill | ResizeMode::SmartCrop => {
+                            ui.add(egui::DragValue::new(&mut app.width).prefix("Width: ").suffix("px"));
+                            ui.add(egui::DragValue::new(&mut app.height).prefix("Height: ").suffix("px"));
+                        }
+                        ResizeMode::Width => {
+                            ui.add(egui::DragValue::new(&mut app.width).prefix("Width: ").suffix("px"));
+                        }
+                        ResizeMode::Height => {
+                            ui.add(egui::DragValue::new(&mut app.height).prefix("Height: ").suffix("px"));
+                        }
+                        ResizeMode::MaxEdge => {
+                            ui.add(egui::DragValue::new(&mut app.max_edge).prefix("Longest edge: ").suffix("px").clamp_range(1..=u32::MAX));
+                        }
+                        ResizeMode::Percentage => {
+                            ui.add(Slider::new(&mut app.scale_percent, 1.0..=400.0).suffix("%"));
+                        }
+                    });
+                    ui.checkbox(&mut app.never_upscale, "Never Upscale");
+                    egui::ComboBox::from_label("Filter")
+                        .selected_text(app.resize_filter.to_string())
+                        .show_ui(ui, |ui| {
+                            for filter in ResizeFilter::ALL {
+                                ui.selectable_value(&mut app.resize_filter, filter, filter.to_string());
+                            }
+                        });
+                    ui.checkbox(&mut app.linear_light, "Linear-light Downscale")
+                        .on_hover_text("Blend in linear light so fine detail doesn't darken (slower)");
+                    ui.checkbox(&mut app.premultiplied_alpha, "Premultiplied Alpha Resize")
+                        .on_hover_text("Keep dark halos off the edges of transparent images");
+                    ui.horizontal(|ui| {
+                        egui::ComboBox::from_label("Alpha")
+                            .selected_text(app.alpha.to_string())
+                            .show_ui(ui, |ui| {
+                                for alpha in AlphaHandling::ALL {
+                                    ui.selectable_value(&mut app.alpha, alpha, alpha.to_string());
+                                }
+                            });
+                        if app.alpha == AlphaHandling::Flatten {
+                            ui.color_edit_button_srgb(&mut app.background);
+                        }
+                    });
+                    ui.checkbox(&mut app.srcset_enabled, "Srcset Widths")
+                        .on_hover_text("Write one output per width, named like photo-640w.webp");
+                    if app.srcset_enabled {
+                        ui.text_edit_singleline(&mut app.srcset_widths);
+                    }
+                    ui.add(Slider::new(&mut app.animation_speed, 0.25..=4.0).logarithmic(true).text("Animation Speed"))
+                        .on_hover_text("Retime animated outputs; 2 plays twice as fast");
                     ui.horizontal(|ui| {
-                        ui.add(egui::DragValue::new(&mut app.width).prefix("Width: ").suffix("px"));
-                        ui.add(egui::DragValue::new(&mut app.height).prefix("Height: ").suffix("px"));
+                        ui.checkbox(&mut app.loop_override, "Loop Count")
+                            .on_hover_text("Replace the source's loop count; 0 loops forever");
+                        if app.loop_override {
+                            ui.add(egui::DragValue::new(&mut app.loop_count).clamp_range(0..=u16::MAX));
+                        }
                     });
                 });
 
@@ -107,7 +250,19 @@ pub fn render(app: &mut App, ctx: &egui::Context) {
                         app.log_messages.lock().push(format!("[{}] No images selected for conversion.", chrono::Local::now().format("%H:%M:%S")));
                     } else {
                         app.log_messages.lock().push(format!("[{}] Starting conversion...", chrono::Local::now().format("%H:%M:%S")));
-                        start_conversion(app);
+                        start_conversion(app, false);
+                    }
+                }
+                ui.add_space(5.0);
+                if ui.add_sized([button_width, 30.0], egui::Button::new("Extract Frames"))
+                    .on_hover_text("Write every frame of the selected images as PNG files")
+                    .clicked()
+                {
+                    if app.input_files.is_empty() {
+                        app.log_messages.lock().push(format!("[{}] No images selected for frame extraction.", chrono::Local::now().format("%H:%M:%S")));
+                    } else {
+                        app.log_messages.lock().push(format!("[{}] Extracting frames...", chrono::Local::now().format("%H:%M:%S")));
+                        start_conversion(app, true);
                     }
                 }
             });
@@ -121,21 +276,32 @@ pub fn render(app: &mut App, ctx: &egui::Context) {
                     ui.set_min_height(ui.available_height() - 250.0); // Adjust this value as needed
                     ui.label(RichText::new("Selected Images:").size(16.0).color(Color32::from_rgb(100, 200, 250)));
                     
-                    egui::ScrollArea::vertical().auto_shrink([false; 2]).show(ui, |ui| {
+                    egui::ScrollArea::both().auto_shrink([false; 2]).show(ui, |ui| {
                         egui::Grid::new("image_details_grid")
-                        .num_columns(6)
+                        .num_columns(16)
                         .striped(true)
                         .show(ui, |ui| {
-                            ui.label(RichText::new("#").strong());
-                            ui.label(RichText::new("Name").strong());
-                            ui.label(RichText::new("Original Size").strong());
-                            ui.label(RichText::new("Compressed Size").strong());
-                            ui.label(RichText::new("Compression Rate").strong());
+                            sort_header(ui, app, SortColumn::Index, "#");
+                            sort_header(ui, app, SortColumn::Name, "Name");
+                            ui.label(RichText::new("Source").strong());
+                            ui.label(RichText::new("Frames").strong());
+                            sort_header(ui, app, SortColumn::OriginalSize, "Original Size");
+                            ui.label(RichText::new("Dimensions").strong());
+                            sort_header(ui, app, SortColumn::CompressedSize, "Compressed Size");
+                            sort_header(ui, app, SortColumn::CompressionRate, "Compression Rate");
+                            ui.label(RichText::new("Format").strong());
+                            ui.label(RichText::new("Mode").strong());
+                            sort_header(ui, app, SortColumn::Quality, "Quality");
+                            ui.label(RichText::new("Metric").strong());
+                            sort_header(ui, app, SortColumn::Psnr, "PSNR");
+                            sort_header(ui, app, SortColumn::Ssim, "SSIM");
+                            sort_header(ui, app, SortColumn::MsSsim, "MS-SSIM");
                             ui.label(RichText::new("Status").strong());
                             ui.end_row();
 
                             let image_details = app.image_details.lock();
-                            for (index, detail) in image_details.iter().enumerate() {
+                            for index in sorted_indices(&image_details, app.sort_column, app.sort_ascending) {
+                                let detail = &image_details[index];
                                 let text_color = if Some(index) == *app.currently_processing.lock() {
                                     Color32::YELLOW
                                 } else {
@@ -143,32 +309,81 @@ pub fn render(app: &mut App, ctx: &egui::Context) {
                                 };
 
                                 ui.label(RichText::new(format!("{}", index + 1)).color(text_color));
-                                ui.label(RichText::new(&detail.name).color(text_color));
+                                let name_cell = ui.horizontal(|ui| {
+                                    ui.label(RichText::new(&detail.name).color(text_color));
+                                    if !detail.scrubbed.is_empty() {
+                                        ui.label(RichText::new("scrubbed").small().color(Color32::from_rgb(255, 165, 0)));
+                                    }
+                                }).response;
+                                let mut name_notes: Vec<String> = detail.srcsets.iter().map(|(format, srcset)| format!("{}: {}", format, srcset)).collect();
+                                if !detail.scrubbed.is_empty() {
+                                    name_notes.push(format!("Sensitive metadata removed: {}", detail.scrubbed.join(", ")));
+                                }
+                                if !name_notes.is_empty() {
+                                    name_cell.on_hover_text(name_notes.join("\n"));
+                                }
+                                ui.label(RichText::new(detail.source_format.as_deref().unwrap_or("-")).color(text_color));
+                                let frames_text = match (detail.frame_count, detail.duration_ms) {
+                                    (Some(frame_count), Some(duration_ms)) => format!("{} ({:.2} s)", frame_count, duration_ms as f64 / 1000.0),
+                                    _ => "-".to_string(),
+                                };
+                                ui.label(RichText::new(frames_text).color(text_color));
                                 ui.label(RichText::new(format!("{:.2} MB", detail.original_size as f64 / (1024.0 * 1024.0))).color(text_color));
+                                let output_dimensions = detail.outputs.first().and_then(|output| output.dimensions).or(detail.output_dimensions);
+                                let dimensions_text = match (detail.original_dimensions, output_dimensions) {
+                                    (Some(original), Some(resized)) if original != resized => {
+                                        format!("{} -> {}", dimensions_label(original), dimensions_label(resized))
+                                    }
+                                    (Some(original), _) => dimensions_label(original),
+                                    (None, _) => "-".to_string(),
+                                };
+                                let dimensions_cell = ui.label(RichText::new(dimensions_text).color(text_color));
+                                let mut resize_notes = Vec::new();
+                                if let Some(orientation) = detail.orientation {
+                                    resize_notes.push(format!("EXIF orientation: {}", orientation));
+                                }
+                                if let Some(conversion) = &detail.color_conversion {
+                                    resize_notes.push(format!("Color: {}", conversion));
+                                }
+                                if !detail.pixel_conversion.is_empty() {
+                                    resize_notes.push(format!("Pixels: {}", detail.pixel_conversion.join(", ")));
+                                }
+                                if let Some(crop) = detail.crop {
+                                    resize_notes.push(format!("Cropped to {}", crop));
+                                }
+                                if let Some((filter, linear)) = detail.resize_filter {
+                                    resize_notes.push(format!("Resampled with {}{}", filter, if linear { " in linear light" } else { "" }));
+                                }
+                                if !resize_notes.is_empty() {
+                                    dimensions_cell.on_hover_text(resize_notes.join("\n"));
+                                }
                                 
-                                if detail.status == "Conversion failed" {
-                                    ui.label(RichText::new("-").color(Color32::RED));
-                                    ui.label(RichText::new("-").color(Color32::RED));
-                                } else {
-                                    ui.label(RichText::new(match detail.compressed_size {
-                                        Some(size) => format!("{:.2} MB", size as f64 / (1024.0 * 1024.0)),
-                                        None => "-".to_string(),
-                                    }).color(text_color));
-                                    ui.label(RichText::new(match detail.compression_rate {
-                                        Some(rate) => format!("{:.2}%", rate * 100.0),
-                                        None => "-".to_string(),
-                                    }).color(text_color));
+                                match detail.outputs.first() {
+                                    Some(primary) => format_output_row(ui, primary, text_color),
+                                    None => {
+                                        let size_color = if detail.status == "Conversion failed" { Color32::RED } else { text_color };
+                                        for column in 0..9 {
+                                            ui.label(RichText::new("-").color(if column < 2 { size_color } else { text_color }));
+                                        }
+                                    }
                                 }
 
-                                let status_color = match detail.status.as_str() {
-                                    "Load successful" => Color32::GREEN,
-                                    "Processing..." => Color32::YELLOW,
-                                    "Conversion successful" => Color32::GREEN,
-                                    "Conversion failed" => Color32::RED,
-                                    _ => text_color,
-                                };
-                                ui.label(RichText::new(&detail.status).color(status_color));
+                                ui.label(RichText::new(&detail.status).color(status_color(&detail.status, text_color)));
                                 ui.end_row();
+
+                                // The main row shows the first format; any others get a sub-row each
+                                for output in detail.outputs.iter().skip(1) {
+                                    for _ in 0..5 {
+                                        ui.label("");
+                                    }
+                                    ui.label(RichText::new(output.dimensions.map_or("-".to_string(), dimensions_label)).color(text_color));
+                                    format_output_row(ui, output, text_color);
+                                    let status_label = ui.label(RichText::new(&output.status).color(status_color(&output.status, text_color)));
+                                    if let Some(error) = &output.error_message {
+                                        status_label.on_hover_text(error);
+                                    }
+                                    ui.end_row();
+                                }
                             }
                             drop(image_details);
                         });
@@ -207,45 +422,270 @@ pub fn render(app: &mut App, ctx: &egui::Context) {
     });
 }
 
-fn start_conversion(app: &mut App) {
+/// Replaces the selection with `files`, filling in what can be read without decoding.
+/// Files under `root` are named by their path relative to it.
+fn select_images(app: &mut App, files: Vec<PathBuf>, root: Option<&Path>) {
+    let image_details: Vec<ImageDetail> = files.iter().map(|path| {
+        let metadata = std::fs::metadata(path).unwrap();
+        let name = match root.and_then(|root| path.strip_prefix(root).ok()) {
+            Some(relative) => relative.to_string_lossy().into_owned(),
+            None => path.file_name().unwrap().to_string_lossy().into_owned(),
+        };
+        let mut detail = ImageDetail::new(name, metadata.len());
+        // Only reads the header, so this stays cheap for large selections
+        detail.original_dimensions = image::io::Reader::open(path)
+            .and_then(|reader| reader.with_guessed_format())
+            .ok()
+            .and_then(|reader| reader.into_dimensions().ok());
+        detail.source_format = source_format_name(path).map(str::to_string);
+        detail
+    }).collect();
+    app.input_files = files;
+    app.input_root = root.map(Path::to_path_buf);
+    *app.image_details.lock() = image_details;
+    app.log_messages.lock().push(format!("[{}] Images selected successfully.", chrono::Local::now().format("%H:%M:%S")));
+}
+
+/// Selects every image under `folder` that passes the folder filters.
+fn select_folder(app: &mut App, folder: &Path) {
+    let split = |patterns: &str| patterns.split(',').map(|pattern| pattern.trim().to_string()).filter(|pattern| !pattern.is_empty()).collect::<Vec<_>>();
+    let kb = |size: u64| (size > 0).then_some(size * 1024);
+    let scan = FolderScan::new()
+        .include(split(&app.folder_include))
+        .exclude(split(&app.folder_exclude))
+        .include_hidden(app.include_hidden)
+        .symlinks(app.symlinks)
+        .size_range(kb(app.min_size_kb), kb(app.max_size_kb));
+    match scan.run(folder) {
+        Ok(files) if files.is_empty() => {
+            app.log_messages.lock().push(format!("[{}] No matching images in {}.", chrono::Local::now().format("%H:%M:%S"), folder.display()));
+        }
+        Ok(files) => {
+            app.log_messages.lock().push(format!("[{}] Found {} images in {}.", chrono::Local::now().format("%H:%M:%S"), files.len(), folder.display()));
+            select_images(app, files, Some(folder));
+        }
+        Err(e) => {
+            app.log_messages.lock().push(format!("[{}] Folder scan failed: {}", chrono::Local::now().format("%H:%M:%S"), e));
+        }
+    }
+}
+
+fn dimensions_label((width, height): (u32, u32)) -> String {
+    format!("{}x{}", width, height)
+}
+
+fn status_color(status: &str, text_color: Color32) -> Color32 {
+    match status {
+        "Load successful" => Color32::GREEN,
+        "Processing..." => Color32::YELLOW,
+        "Conversion successful" | "Frames extracted" => Color32::GREEN,
+        "Conversion failed" => Color32::RED,
+        "Over target size" | "Below target quality" | "First frame only" | "Metadata dropped" | "Metrics unavailable" => Color32::from_rgb(255, 165, 0),
+        _ => text_color,
+    }
+}
+
+/// Result cells (Compressed Size through MS-SSIM) for one output format.
+fn format_output_row(ui: &mut egui::Ui, output: &FormatOutput, text_color: Color32) {
+    let failed = output.status == "Conversion failed";
+    let size_color = if failed { Color32::RED } else { text_color };
+    ui.label(RichText::new(match output.compressed_size {
+        Some(size) => format!("{:.2} MB", size as f64 / (1024.0 * 1024.0)),
+        None => "-".to_string(),
+    }).color(size_color));
+    ui.label(RichText::new(match output.compression_rate {
+        Some(rate) => format!("{:.2}%", rate * 100.0),
+        None => "-".to_string(),
+    }).color(size_color));
+    ui.label(RichText::new(output.label()).color(text_color));
+    let mode_text = match (output.encoding_mode, &output.mode_reason) {
+        (Some(mode), Some(_)) => format!("{} (auto)", mode),
+        (Some(mode), None) => mode.to_string(),
+        (None, _) => "-".to_string(),
+    };
+    let mode_label = ui.label(RichText::new(mode_text).color(text_color));
+    if let Some(reason) = &output.mode_reason {
+        mode_label.on_hover_text(reason);
+    }
+    ui.label(RichText::new(match output.quality {
+        Some(quality) => format!("{:.0}", quality),
+        None => "-".to_string(),
+    }).color(text_color));
+    ui.label(RichText::new(match output.measured_metric {
+        Some((metric, value)) => format!("{} {}", metric, metric.format_value(value)),
+        None => "-".to_string(),
+    }).color(text_color));
+    match output.fidelity {
+        Some(metrics) => {
+            ui.label(RichText::new(QualityMetric::Psnr.format_value(metrics.psnr)).color(text_color));
+            ui.label(RichText::new(QualityMetric::Ssim.format_value(metrics.ssim)).color(text_color));
+            ui.label(RichText::new(format!("{:.4}", metrics.ms_ssim)).color(text_color));
+        }
+        None => {
+            for _ in 0..3 {
+                ui.label(RichText::new("-").color(text_color));
+            }
+        }
+    }
+}
+
+/// Controls for the encoder's basic or advanced options, built from its schema.
+fn encoder_settings_ui(ui: &mut egui::Ui, encoder: &mut dyn ImageEncoder, advanced: bool) {
+    for option in encoder.options_schema().into_iter().filter(|option| option.advanced == advanced) {
+        if let Some(value) = encoder.option(option.key) {
+            if let Some(value) = option_ui(ui, &option, value) {
+                encoder.set_option(option.key, value);
+            }
+        }
+    }
+}
+
+/// Draws one option, returning the new value if the user changed it.
+fn option_ui(ui: &mut egui::Ui, option: &EncoderOption, value: OptionValue) -> Option<OptionValue> {
+    match (&option.kind, value) {
+        (OptionKind::Float { min, max }, OptionValue::Float(mut value)) => {
+            ui.add(Slider::new(&mut value, *min..=*max).text(&option.label)).changed().then_some(OptionValue::Float(value))
+        }
+        (OptionKind::Int { min, max }, OptionValue::Int(mut value)) => {
+            ui.add(Slider::new(&mut value, *min..=*max).text(&option.label)).changed().then_some(OptionValue::Int(value))
+        }
+        (OptionKind::Bool, OptionValue::Bool(mut value)) => {
+            ui.checkbox(&mut value, &option.label).changed().then_some(OptionValue::Bool(value))
+        }
+        (OptionKind::Choice(labels), OptionValue::Choice(selected)) => {
+            let mut choice = selected;
+            egui::ComboBox::from_label(&option.label)
+                .selected_text(labels.get(selected).map(String::as_str).unwrap_or("-"))
+                .show_ui(ui, |ui| {
+                    for (index, label) in labels.iter().enumerate() {
+                        ui.selectable_value(&mut choice, index, label);
+                    }
+                });
+            (choice != selected).then_some(OptionValue::Choice(choice))
+        }
+        _ => None,
+    }
+}
+
+/// Results grid columns that can be sorted by clicking their header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortColumn {
+    Index,
+    Name,
+    OriginalSize,
+    CompressedSize,
+    CompressionRate,
+    Quality,
+    Psnr,
+    Ssim,
+    MsSsim,
+}
+
+fn sort_header(ui: &mut egui::Ui, app: &mut App, column: SortColumn, label: &str) {
+    let arrow = match app.sort_column {
+        Some(current) if current == column => if app.sort_ascending { " ^" } else { " v" },
+        _ => "",
+    };
+    if ui.add(egui::Button::new(RichText::new(format!("{}{}", label, arrow)).strong()).frame(false)).clicked() {
+        if app.sort_column == Some(column) {
+            app.sort_ascending = !app.sort_ascending;
+        } else {
+            app.sort_column = Some(column);
+            app.sort_ascending = true;
+        }
+    }
+}
+
+fn sort_key(index: usize, detail: &ImageDetail, column: SortColumn) -> Option<f64> {
+    match column {
+        SortColumn::Index | SortColumn::Name => Some(index as f64),
+        SortColumn::OriginalSize => Some(detail.original_size as f64),
+        SortColumn::CompressedSize => detail.compressed_size.map(|size| size as f64),
+        SortColumn::CompressionRate => detail.compression_rate.map(|rate| rate as f64),
+        SortColumn::Quality => detail.quality.map(|quality| quality as f64),
+        SortColumn::Psnr => detail.fidelity.map(|metrics| metrics.psnr),
+        SortColumn::Ssim => detail.fidelity.map(|metrics| metrics.ssim),
+        SortColumn::MsSsim => detail.fidelity.map(|metrics| metrics.ms_ssim),
+    }
+}
+
+/// Row order for the results grid; `ImageDetail` indices stay stable for incoming updates.
+fn sorted_indices(details: &[ImageDetail], column: Option<SortColumn>, ascending: bool) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..details.len()).collect();
+    if let Some(column) = column {
+        indices.sort_by(|&a, &b| {
+            let ordering = match column {
+                SortColumn::Name => details[a].name.cmp(&details[b].name),
+                _ => sort_key(a, &details[a], column)
+                    .partial_cmp(&sort_key(b, &details[b], column))
+                    .unwrap_or(std::cmp::Ordering::Equal),
+            };
+            if ascending { ordering } else { ordering.reverse() }
+        });
+    }
+    indices
+}
+
+/// Widths from a comma- or space-separated list; anything that isn't a positive number is ignored.
+fn parse_widths(text: &str) -> Vec<u32> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter_map(|width| width.trim().parse().ok())
+        .filter(|&width| width > 0)
+        .collect()
+}
+
+/// Runs the selection on a background thread; `extract_frames` writes PNG frames instead of converting.
+fn start_conversion(app: &mut App, extract_frames: bool) {
     let input_files = app.input_files.clone();
     let output_directory = app.output_directory.clone().unwrap_or_else(|| {
-        input_files.first().and_then(|path| path.parent().map(|p| p.to_path_buf()))
+        app.input_root.clone()
+            .or_else(|| input_files.first().and_then(|path| path.parent().map(|p| p.to_path_buf())))
             .unwrap_or_else(|| std::env::current_dir().unwrap())
     });
-    let resize_enabled = app.resize_enabled;
-    let width = app.width;
-    let height = app.height;
-    let quality_enabled = app.quality_enabled;
-    let compression_quality = app.compression_quality;
-    let rename_enabled = app.rename_enabled;
-    let output_filename = app.output_filename.clone();
-    let conversion_progress = app.conversion_progress.clone();
-    let log_messages = app.log_messages.clone();
-    let original_sizes = app.original_sizes.clone();
-    let compressed_sizes = app.compressed_sizes.clone();
-    let image_details = app.image_details.clone();
+    let options = ConversionOptions {
+        auto_orient: app.auto_orient,
+        resize_enabled: app.resize_enabled,
+        resize_mode: app.resize_mode,
+        width: app.width,
+        height: app.height,
+        max_edge: app.max_edge,
+        scale_percent: app.scale_percent,
+        never_upscale: app.never_upscale,
+        resize_filter: app.resize_filter,
+        linear_light: app.linear_light,
+        premultiplied_alpha: app.premultiplied_alpha,
+        alpha: app.alpha,
+        background: app.background,
+        rename_enabled: app.rename_enabled,
+        output_filename: app.output_filename.clone(),
+        encoders: app.selected_encoders.clone(),
+        srcset_widths: if app.srcset_enabled { parse_widths(&app.srcset_widths) } else { Vec::new() },
+        compute_metrics: app.compute_metrics,
+        metadata: app.metadata_policy,
+        color_management: app.color_management,
+        animation_speed: app.animation_speed,
+        loop_count: app.loop_override.then_some(app.loop_count),
+    };
+    let mut job = ConversionJob::new(input_files, output_directory).options(options);
+    if let Some(root) = &app.input_root {
+        job = job.input_root(root);
+    }
 
     let (sender, receiver) = channel();
     app.conversion_receiver = Some(receiver);
+    app.conversion_start_time = Some(Instant::now());
+
+    // The thread gets a snapshot of the settings, so edits during a run apply to the next one
+    let converter = Converter::new()
+        .encoders(app.encoders.clone())
+        .log_messages(app.log_messages.clone())
+        .updates(sender);
 
     std::thread::spawn(move || {
-        image_processing::convert_images(
-            input_files,
-            output_directory,
-            resize_enabled,
-            width,
-            height,
-            quality_enabled,
-            compression_quality,
-            rename_enabled,
-            output_filename,
-            conversion_progress,
-            log_messages,
-            original_sizes,
-            compressed_sizes,
-            image_details,
-            sender,
-        );
+        if extract_frames {
+            converter.extract_frames(&job);
+        } else {
+            converter.run(&job);
+        }
     });
-}
\ No newline at end of file
+}
@@ -0,0 +1,620 @@
+// cli.rs
+use clap::{Parser, ValueEnum};
+use jpg_to_webp_coder::image_processing::is_supported_input;
+use jpg_to_webp_coder::{
+    AlphaHandling, AvifEncodeSettings, AvifEncoder, ColorManagement, ConversionJob, ConversionOptions, ConversionUpdate, Converter, EncodingMode, FolderScan,
+    JpegEncoder, MetadataPolicy, QualityMetric, ResizeFilter, ResizeMode, SymlinkPolicy, WebpEncodeSettings, WebpEncoder,
+};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::mpsc::channel;
+
+/// Convert images to WebP, AVIF or JPEG without the GUI.
+#[derive(Parser)]
+#[command(name = "jpg_to_webp_cli", version)]
+struct Cli {
+    /// Input files, glob patterns or directories (directories are searched recursively with --recursive)
+    #[arg(required = true)]
+    inputs: Vec<String>,
+
+    /// Search directories recursively; outputs keep the subfolders they were found in
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Only pick files matching these globs when searching recursively, comma-separated (e.g. "*.png,icons/**/*")
+    #[arg(long, value_delimiter = ',', requires = "recursive")]
+    include: Vec<String>,
+
+    /// Skip files and folders matching these globs when searching recursively, comma-separated
+    #[arg(long, value_delimiter = ',', requires = "recursive")]
+    exclude: Vec<String>,
+
+    /// Pick up hidden files and folders when searching recursively
+    #[arg(long, requires = "recursive")]
+    hidden: bool,
+
+    /// Follow symbolic links when searching recursively
+    #[arg(long, requires = "recursive")]
+    follow_symlinks: bool,
+
+    /// Skip files smaller than this many KB when searching recursively
+    #[arg(long, value_name = "KB", requires = "recursive")]
+    min_size: Option<u64>,
+
+    /// Skip files larger than this many KB when searching recursively
+    #[arg(long, value_name = "KB", requires = "recursive")]
+    max_size: Option<u64>,
+
+    /// Output directory (defaults to the directory of the first input, or the first directory searched recursively)
+    #[arg(short, long)]
+    output_dir: Option<PathBuf>,
+
+    /// Compression quality, 1-100 (defaults to 80)
+    #[arg(short, long, value_parser = parse_quality)]
+    quality: Option<f32>,
+
+    /// Keep the stored pixel order instead of applying the EXIF Orientation tag
+    #[arg(long)]
+    no_auto_orient: bool,
+
+    /// Resize width in pixels (alone: scale to this width, keeping the aspect ratio)
+    #[arg(long, required_if_eq_any = BOX_RESIZE_MODES)]
+    width: Option<u32>,
+
+    /// Resize height in pixels (alone: scale to this height, keeping the aspect ratio)
+    #[arg(long, required_if_eq_any = BOX_RESIZE_MODES)]
+    height: Option<u32>,
+
+    /// How --width and --height together are applied; every mode but fit needs both
+    #[arg(long, value_enum, default_value_t = ResizeArg::Fit)]
+    resize_mode: ResizeArg,
+
+    /// Scale so the longest edge is this many pixels
+    #[arg(long, value_name = "PX", conflicts_with_all = ["width", "height", "scale"], value_parser = clap::value_parser!(u32).range(1..))]
+    max_edge: Option<u32>,
+
+    /// Scale by this percentage
+    #[arg(long, value_name = "PERCENT", conflicts_with_all = ["width", "height"])]
+    scale: Option<f32>,
+
+    /// Never enlarge images that are smaller than the target
+    #[arg(long)]
+    no_upscale: bool,
+
+    /// Resampling filter for resizing
+    #[arg(long, value_enum, default_value_t = FilterArg::Lanczos3)]
+    filter: FilterArg,
+
+    /// Downscale in linear light rather than gamma-encoded sRGB
+    #[arg(long)]
+    linear_light: bool,
+
+    /// Resize transparent images with straight rather than premultiplied alpha
+    #[arg(long)]
+    no_premultiply: bool,
+
+    /// What to do with transparency
+    #[arg(long, value_enum, default_value_t = AlphaArg::Keep)]
+    alpha: AlphaArg,
+
+    /// Background for --alpha flatten, as RRGGBB hex
+    #[arg(long, value_name = "RRGGBB", default_value = "ffffff", value_parser = parse_color)]
+    background: [u8; 3],
+
+    /// Srcset widths, comma-separated (e.g. 320,640,1280); writes <name>-<width>w.<ext> per width,
+    /// skipping widths larger than the image
+    #[arg(long, value_name = "WIDTHS", value_delimiter = ',', value_parser = clap::value_parser!(u32).range(1..))]
+    srcset: Vec<u32>,
+
+    /// Write output as <NAME>.<ext> instead of reusing the input file name;
+    /// with several inputs they are numbered <NAME>-1.<ext>, <NAME>-2.<ext>, ...
+    #[arg(long, value_name = "NAME")]
+    rename: Option<String>,
+
+    /// Output formats, comma-separated; each input is decoded once and written in every format
+    #[arg(short, long, value_enum, value_delimiter = ',', default_values_t = [FormatArg::Webp])]
+    format: Vec<FormatArg>,
+
+    /// AVIF color quality, 1-100
+    #[arg(long, default_value_t = 70.0, value_parser = parse_quality)]
+    avif_quality: f32,
+
+    /// AVIF alpha quality, 1-100
+    #[arg(long, default_value_t = 80.0, value_parser = parse_quality)]
+    avif_alpha_quality: f32,
+
+    /// AVIF encoder speed, 1 (slowest, smallest) to 10 (fastest)
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u8).range(1..=10))]
+    avif_speed: u8,
+
+    /// JPEG fallback quality, 1-100
+    #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u8).range(1..=100))]
+    jpeg_quality: u8,
+
+    /// WebP bitstream to produce (in the lossless modes quality sets compression effort; auto picks per image)
+    #[arg(long, value_enum, default_value_t = ModeArg::Lossy)]
+    mode: ModeArg,
+
+    /// Search for the highest quality whose output fits in this many KB
+    #[arg(long, value_name = "KB", conflicts_with_all = ["mode", "quality"], value_parser = clap::value_parser!(u64).range(1..))]
+    target_size: Option<u64>,
+
+    /// Search for the lowest quality whose output reaches this SSIM (0-1)
+    #[arg(long, value_name = "SSIM", conflicts_with_all = ["mode", "quality", "target_size", "target_psnr"])]
+    target_ssim: Option<f64>,
+
+    /// Search for the lowest quality whose output reaches this PSNR in dB
+    #[arg(long, value_name = "DB", conflicts_with_all = ["mode", "quality", "target_size"])]
+    target_psnr: Option<f64>,
+
+    /// Decode each output and report PSNR, SSIM and MS-SSIM (costs an extra decode)
+    #[arg(long)]
+    metrics: bool,
+
+    /// Source EXIF, XMP and ICC profile to carry into WebP and JPEG outputs (AVIF outputs drop them)
+    #[arg(long, value_enum, default_value_t = MetadataArg::Strip)]
+    metadata: MetadataArg,
+
+    /// What to do with embedded ICC profiles
+    #[arg(long, value_enum, default_value_t = ColorArg::Convert)]
+    color: ColorArg,
+
+    /// Playback speed of animated outputs relative to the source (2 is twice as fast)
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    speed: f32,
+
+    /// Times animated outputs play, 0 for forever (defaults to the source's count)
+    #[arg(long)]
+    loop_count: Option<u16>,
+
+    /// Write every frame of each input as <name>-frame-001.png and so on instead of converting
+    #[arg(long)]
+    extract_frames: bool,
+
+    /// Near-lossless preprocessing, 0 (strongest) to 100 (none)
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u8).range(0..=100))]
+    near_lossless: u8,
+
+    /// Speed/size tradeoff, 0 (fast) to 6 (slower, smaller)
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(0..=6))]
+    method: u8,
+
+    /// Spatial noise shaping strength, 0-100
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u8).range(0..=100))]
+    sns_strength: u8,
+
+    /// Deblocking filter strength, 0-100
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u8).range(0..=100))]
+    filter_strength: u8,
+
+    /// Deblocking filter sharpness, 0 (sharpest) to 7
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=7))]
+    filter_sharpness: u8,
+
+    /// Number of segments, 1-4
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..=4))]
+    segments: u8,
+
+    /// Number of entropy-analysis passes, 1-10
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=10))]
+    pass: u8,
+
+    /// Preprocessing: 0 none, 1 segment-smooth, 2 pseudo-random dithering
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=2))]
+    preprocessing: u8,
+
+    /// Use the slower but more accurate RGB->YUV conversion
+    #[arg(long)]
+    sharp_yuv: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    Webp,
+    Avif,
+    Jpeg,
+}
+
+impl FormatArg {
+    /// Name of the registered encoder backend.
+    fn encoder_name(self) -> &'static str {
+        match self {
+            FormatArg::Webp => "WebP",
+            FormatArg::Avif => "AVIF",
+            FormatArg::Jpeg => "JPEG",
+        }
+    }
+}
+
+/// Resize modes that only make sense for a full --width x --height box.
+const BOX_RESIZE_MODES: [(&str, &str); 3] = [("resize_mode", "fill"), ("resize_mode", "smart"), ("resize_mode", "exact")];
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ResizeArg {
+    /// Fit within the box
+    Fit,
+    /// Cover the box and center-crop
+    Fill,
+    /// Cover the box and crop to the most detailed, colorful or skin-toned window
+    Smart,
+    /// Stretch to the box
+    Exact,
+}
+
+impl From<ResizeArg> for ResizeMode {
+    fn from(mode: ResizeArg) -> Self {
+        match mode {
+            ResizeArg::Fit => ResizeMode::Fit,
+            ResizeArg::Fill => ResizeMode::Fill,
+            ResizeArg::Smart => ResizeMode::SmartCrop,
+            ResizeArg::Exact => ResizeMode::Exact,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FilterArg {
+    /// Nearest neighbor, for pixel art
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<FilterArg> for ResizeFilter {
+    fn from(filter: FilterArg) -> Self {
+        match filter {
+            FilterArg::Nearest => ResizeFilter::Nearest,
+            FilterArg::Triangle => ResizeFilter::Triangle,
+            FilterArg::CatmullRom => ResizeFilter::CatmullRom,
+            FilterArg::Gaussian => ResizeFilter::Gaussian,
+            FilterArg::Lanczos3 => ResizeFilter::Lanczos3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MetadataArg {
+    /// EXIF, XMP and ICC profile
+    Keep,
+    /// Everything but GPS, serial numbers, owner names and thumbnails
+    Scrub,
+    /// ICC profile only
+    Icc,
+    Strip,
+}
+
+impl From<MetadataArg> for MetadataPolicy {
+    fn from(metadata: MetadataArg) -> Self {
+        match metadata {
+            MetadataArg::Keep => MetadataPolicy::KeepAll,
+            MetadataArg::Scrub => MetadataPolicy::ScrubSensitive,
+            MetadataArg::Icc => MetadataPolicy::IccOnly,
+            MetadataArg::Strip => MetadataPolicy::StripAll,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorArg {
+    /// Transform the pixels to sRGB
+    Convert,
+    /// Keep the pixels and embed the profile (WebP and JPEG; AVIF outputs drop it)
+    Preserve,
+    /// Treat the pixels as sRGB
+    Ignore,
+}
+
+impl From<ColorArg> for ColorManagement {
+    fn from(color: ColorArg) -> Self {
+        match color {
+            ColorArg::Convert => ColorManagement::ConvertToSrgb,
+            ColorArg::Preserve => ColorManagement::PreserveProfile,
+            ColorArg::Ignore => ColorManagement::Ignore,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AlphaArg {
+    Keep,
+    /// Composite onto --background
+    Flatten,
+}
+
+impl From<AlphaArg> for AlphaHandling {
+    fn from(alpha: AlphaArg) -> Self {
+        match alpha {
+            AlphaArg::Keep => AlphaHandling::Keep,
+            AlphaArg::Flatten => AlphaHandling::Flatten,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ModeArg {
+    Lossy,
+    Lossless,
+    NearLossless,
+    Auto,
+}
+
+impl From<ModeArg> for EncodingMode {
+    fn from(mode: ModeArg) -> Self {
+        match mode {
+            ModeArg::Lossy => EncodingMode::Lossy,
+            ModeArg::Lossless => EncodingMode::Lossless,
+            ModeArg::NearLossless => EncodingMode::NearLossless,
+            ModeArg::Auto => EncodingMode::Auto,
+        }
+    }
+}
+
+fn parse_quality(value: &str) -> Result<f32, String> {
+    let quality: f32 = value.parse().map_err(|_| format!("`{}` is not a number", value))?;
+    if (1.0..=100.0).contains(&quality) {
+        Ok(quality)
+    } else {
+        Err("quality must be between 1 and 100".to_string())
+    }
+}
+
+fn parse_speed(value: &str) -> Result<f32, String> {
+    let speed: f32 = value.parse().map_err(|_| format!("`{}` is not a number", value))?;
+    if speed > 0.0 && speed.is_finite() {
+        Ok(speed)
+    } else {
+        Err("speed must be greater than 0".to_string())
+    }
+}
+
+fn parse_color(value: &str) -> Result<[u8; 3], String> {
+    let hex = value.trim_start_matches('#');
+    let channel = |index: usize| hex.get(index * 2..index * 2 + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok());
+    match (hex.len(), channel(0), channel(1), channel(2)) {
+        (6, Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(format!("`{}` is not an RRGGBB color", value)),
+    }
+}
+
+/// Expands directories (recursively through `scan`, when given) and glob patterns into the list of images to convert,
+/// along with the directories searched recursively.
+fn collect_inputs(inputs: &[String], scan: Option<&FolderScan>) -> Result<(Vec<PathBuf>, Vec<PathBuf>), String> {
+    let mut files = Vec::new();
+    let mut roots = Vec::new();
+    for input in inputs {
+        let path = PathBuf::from(input);
+        if let (true, Some(scan)) = (path.is_dir(), scan) {
+            files.extend(scan.run(&path)?);
+            roots.push(path);
+        } else if path.is_dir() {
+            let entries = std::fs::read_dir(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let mut dir_files: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && is_supported_input(path))
+                .collect();
+            dir_files.sort();
+            files.extend(dir_files);
+        } else if path.is_file() {
+            files.push(path);
+        } else {
+            let matches = glob::glob(input).map_err(|e| format!("{}: {}", input, e))?;
+            let before = files.len();
+            files.extend(matches.filter_map(Result::ok).filter(|path| path.is_file() && is_supported_input(path)));
+            if files.len() == before {
+                return Err(format!("{}: no matching images", input));
+            }
+        }
+    }
+    Ok((files, roots))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let scan = cli.recursive.then(|| {
+        FolderScan::new()
+            .include(cli.include.clone())
+            .exclude(cli.exclude.clone())
+            .include_hidden(cli.hidden)
+            .symlinks(if cli.follow_symlinks { SymlinkPolicy::Follow } else { SymlinkPolicy::Skip })
+            .size_range(cli.min_size.map(|kb| kb * 1024), cli.max_size.map(|kb| kb * 1024))
+    });
+    let (input_files, input_roots) = match collect_inputs(&cli.inputs, scan.as_ref()) {
+        Ok(inputs) => inputs,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let output_directory = cli.output_dir.unwrap_or_else(|| {
+        input_roots.first().cloned()
+            .or_else(|| input_files.first().and_then(|path| path.parent().map(|p| p.to_path_buf())))
+            .unwrap_or_else(|| std::env::current_dir().unwrap())
+    });
+
+    let mut webp = WebpEncoder::new()
+        .mode(cli.mode.into())
+        .settings(WebpEncodeSettings {
+            method: cli.method,
+            sns_strength: cli.sns_strength,
+            filter_strength: cli.filter_strength,
+            filter_sharpness: cli.filter_sharpness,
+            segments: cli.segments,
+            pass: cli.pass,
+            preprocessing: cli.preprocessing,
+            sharp_yuv: cli.sharp_yuv,
+            near_lossless: cli.near_lossless,
+        });
+    if let Some(quality) = cli.quality {
+        webp = webp.quality(quality);
+    }
+    if let Some(target_size) = cli.target_size {
+        webp = webp.target_size(target_size * 1024);
+    }
+    if let Some(target) = cli.target_ssim {
+        webp = webp.target_quality(QualityMetric::Ssim, target);
+    }
+    if let Some(target) = cli.target_psnr {
+        webp = webp.target_quality(QualityMetric::Psnr, target);
+    }
+    let avif = AvifEncoder::new(AvifEncodeSettings {
+        quality: cli.avif_quality,
+        alpha_quality: cli.avif_alpha_quality,
+        speed: cli.avif_speed,
+    });
+
+    let mut options = ConversionOptions::new()
+        .encoders(cli.format.iter().map(|format| format.encoder_name()))
+        .srcset_widths(cli.srcset)
+        .compute_metrics(cli.metrics)
+        .metadata(cli.metadata.into())
+        .color_management(cli.color.into())
+        .auto_orient(!cli.no_auto_orient)
+        .animation_speed(cli.speed);
+    if let Some(loop_count) = cli.loop_count {
+        options = options.loop_count(loop_count);
+    }
+    match (cli.width, cli.height) {
+        (Some(width), Some(height)) => options = options.resize(width, height).resize_mode(cli.resize_mode.into()),
+        (Some(width), None) => options = options.resize(width, 0).resize_mode(ResizeMode::Width),
+        (None, Some(height)) => options = options.resize(0, height).resize_mode(ResizeMode::Height),
+        (None, None) => {}
+    }
+    if let Some(max_edge) = cli.max_edge {
+        options = options.max_edge(max_edge);
+    }
+    if let Some(percent) = cli.scale {
+        options = options.scale(percent);
+    }
+    options = options
+        .never_upscale(cli.no_upscale)
+        .resize_filter(cli.filter.into())
+        .linear_light(cli.linear_light)
+        .premultiplied_alpha(!cli.no_premultiply)
+        .alpha(cli.alpha.into());
+    options.background = cli.background;
+    if let Some(name) = cli.rename {
+        options = options.rename(name);
+    }
+
+    let names: Vec<String> = input_files.iter().map(|path| path.display().to_string()).collect();
+    let mut job = ConversionJob::new(input_files, output_directory).options(options);
+    for root in input_roots {
+        job = job.input_root(root);
+    }
+
+    let (sender, receiver) = channel();
+    let converter = Converter::new()
+        .encoders(vec![Box::new(webp), Box::new(avif), Box::new(JpegEncoder::new(cli.jpeg_quality))])
+        .updates(sender);
+    let extract_frames = cli.extract_frames;
+    let handle = std::thread::spawn(move || if extract_frames { converter.extract_frames(&job) } else { converter.run(&job) });
+
+    for update in receiver {
+        match update {
+            ConversionUpdate::StatusUpdate(index, status, error_message) => match error_message {
+                Some(error) => eprintln!("{}: {} ({})", names[index], status, error),
+                None => eprintln!("{}: {}", names[index], status),
+            },
+            ConversionUpdate::AutoDecision(index, mode, reason) => eprintln!("{}: auto chose {}, {}", names[index], mode, reason),
+            ConversionUpdate::FidelityMeasured(index, metrics) => eprintln!(
+                "{}: PSNR {}, SSIM {}, MS-SSIM {:.4}",
+                names[index],
+                QualityMetric::Psnr.format_value(metrics.psnr),
+                QualityMetric::Ssim.format_value(metrics.ssim),
+                metrics.ms_ssim,
+            ),
+            ConversionUpdate::FormatProcessed(index, output) => match output.compressed_size {
+                Some(size) => eprintln!("{}: {} {} bytes", names[index], output.label(), size),
+                None => eprintln!("{}: {} failed", names[index], output.label()),
+            },
+            ConversionUpdate::SrcsetGenerated(index, format, srcset) => eprintln!("{}: srcset ({}) \"{}\"", names[index], format, srcset),
+            ConversionUpdate::Dimensions(index, original, resized) if original != resized => {
+                eprintln!("{}: {}x{} -> {}x{}", names[index], original.0, original.1, resized.0, resized.1)
+            }
+            ConversionUpdate::FilterUsed(index, filter, linear) => {
+                eprintln!("{}: resampled with {}{}", names[index], filter, if linear { " in linear light" } else { "" })
+            }
+            ConversionUpdate::Oriented(index, orientation) => eprintln!("{}: EXIF orientation, {}", names[index], orientation),
+            ConversionUpdate::MetadataScrubbed(index, scrubbed) => eprintln!("{}: scrubbed {}", names[index], scrubbed.join(", ")),
+            ConversionUpdate::ColorConverted(index, conversion) => eprintln!("{}: converted {}", names[index], conversion),
+            ConversionUpdate::Animated(index, frame_count, duration_ms) => {
+                eprintln!("{}: {} frames, {:.2} s", names[index], frame_count, duration_ms as f64 / 1000.0)
+            }
+            ConversionUpdate::PixelsConverted(index, steps) => eprintln!("{}: {}", names[index], steps.join(", ")),
+            ConversionUpdate::Cropped(index, crop) => eprintln!("{}: cropped to {}", names[index], crop),
+            ConversionUpdate::Progress(completed, total) => eprintln!("[{}/{}]", completed, total),
+            ConversionUpdate::QualityUsed(index, quality) => eprintln!("{}: quality {:.0}", names[index], quality),
+            ConversionUpdate::MetricMeasured(index, metric, value) => eprintln!("{}: {} {}", names[index], metric, metric.format_value(value)),
+            ConversionUpdate::ResultsUpdate(..)
+            | ConversionUpdate::ImageProcessed(..)
+            | ConversionUpdate::EncodingUsed(..)
+            | ConversionUpdate::SourceFormat(..)
+            | ConversionUpdate::Dimensions(..) => {}
+            ConversionUpdate::Completed => break,
+        }
+    }
+
+    let details = handle.join().expect("conversion thread panicked");
+    let failed = details.iter().filter(|detail| detail.status == "Conversion failed").count();
+    if cli.extract_frames {
+        let frames: usize = details.iter().filter(|detail| detail.status == "Frames extracted").map(|detail| detail.frame_count.unwrap_or(1)).sum();
+        eprintln!("Extracted {} frames from {} of {} images", frames, details.len() - failed, details.len());
+        return if failed > 0 { ExitCode::FAILURE } else { ExitCode::SUCCESS };
+    }
+    let over_budget = details.iter().filter(|detail| detail.status == "Over target size").count();
+    let below_quality = details.iter().filter(|detail| detail.status == "Below target quality").count();
+    let metadata_dropped = details.iter().filter(|detail| detail.status == "Metadata dropped").count();
+    let converted = details.iter().filter(|detail| detail.compressed_size.is_some());
+    let original: u64 = converted.clone().map(|detail| detail.original_size).sum();
+    let compressed: u64 = converted.filter_map(|detail| detail.compressed_size).sum();
+    eprintln!(
+        "Converted {} of {} images ({:.2} MB -> {:.2} MB)",
+        details.len() - failed,
+        details.len(),
+        original as f64 / (1024.0 * 1024.0),
+        compressed as f64 / (1024.0 * 1024.0),
+    );
+    if cli.format.len() > 1 {
+        for format in &cli.format {
+            let size: u64 = details.iter()
+                .flat_map(|detail| &detail.outputs)
+                .filter(|output| output.format == format.encoder_name())
+                .filter_map(|output| output.compressed_size)
+                .sum();
+            eprintln!("  {}: {:.2} MB", format.encoder_name(), size as f64 / (1024.0 * 1024.0));
+        }
+    }
+    if over_budget > 0 {
+        eprintln!("warning: {} images could not reach the target size", over_budget);
+    }
+    if below_quality > 0 {
+        eprintln!("warning: {} images could not reach the target quality", below_quality);
+    }
+    if metadata_dropped > 0 {
+        eprintln!("warning: {} images were written without their metadata", metadata_dropped);
+    }
+
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_resize_modes_need_width_and_height() {
+        let parse = |args: &[&str]| Cli::try_parse_from(["cli", "in.png"].iter().chain(args));
+        assert!(parse(&["--resize-mode", "smart"]).is_err());
+        assert!(parse(&["--resize-mode", "fill", "--width", "100"]).is_err());
+        assert!(parse(&["--resize-mode", "smart", "--width", "100", "--height", "80"]).is_ok());
+        assert!(parse(&["--width", "100"]).is_ok());
+    }
+}
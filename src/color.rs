@@ -0,0 +1,416 @@
+// color.rs
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive};
+
+/// sRGB primaries adapted to the D50 profile connection space, one column per channel (as in the ICC sRGB profile).
+const SRGB_TO_XYZ_D50: [[f64; 3]; 3] = [
+    [0.436_074_7, 0.385_064_9, 0.143_080_4],
+    [0.222_504_5, 0.716_878_6, 0.060_616_9],
+    [0.013_932_2, 0.097_104_5, 0.714_173_3],
+];
+/// How far a profile's colorants may stray from sRGB's and still count as sRGB.
+const SRGB_TOLERANCE: f64 = 0.002;
+
+/// What happens to images that embed an ICC profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorManagement {
+    /// Transform the pixels to sRGB, so every viewer shows the intended colors.
+    ConvertToSrgb,
+    /// Keep the pixels and embed the source profile in outputs that can hold one.
+    PreserveProfile,
+    /// Treat the pixels as sRGB; profiles are handled by the metadata policy alone.
+    Ignore,
+}
+
+impl ColorManagement {
+    pub const ALL: [ColorManagement; 3] = [ColorManagement::ConvertToSrgb, ColorManagement::PreserveProfile, ColorManagement::Ignore];
+}
+
+impl std::fmt::Display for ColorManagement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColorManagement::ConvertToSrgb => write!(f, "Convert to sRGB"),
+            ColorManagement::PreserveProfile => write!(f, "Preserve profile"),
+            ColorManagement::Ignore => write!(f, "Assume sRGB"),
+        }
+    }
+}
+
+/// An RGB matrix/TRC ICC profile: per-channel tone curves, then a matrix to the D50 PCS.
+///
+/// This covers Display P3, Adobe RGB, ProPhoto and most camera profiles; LUT-based profiles are rejected.
+#[derive(Clone, Debug)]
+pub struct IccProfile {
+    /// Profile description, e.g. "Display P3".
+    pub description: String,
+    to_xyz: [[f64; 3]; 3],
+    curves: [ToneCurve; 3],
+}
+
+#[derive(Clone, Debug)]
+enum ToneCurve {
+    Gamma(f64),
+    /// Evenly spaced samples over 0-1, interpolated linearly.
+    Table(Vec<f64>),
+    /// ICC parametric curve: `(a*x + b)^g + e` from `d` up, `c*x + f` below.
+    Parametric { g: f64, a: f64, b: f64, c: f64, d: f64, e: f64, f: f64 },
+}
+
+impl ToneCurve {
+    /// Encoded value to linear light.
+    fn eval(&self, x: f64) -> f64 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            ToneCurve::Gamma(gamma) => x.powf(*gamma),
+            ToneCurve::Table(table) => {
+                let position = x * (table.len() - 1) as f64;
+                let index = (position.floor() as usize).min(table.len() - 2);
+                let fraction = position - index as f64;
+                table[index] + (table[index + 1] - table[index]) * fraction
+            }
+            ToneCurve::Parametric { g, a, b, c, d, e, f } => {
+                if x >= *d {
+                    (a * x + b).max(0.0).powf(*g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        }
+    }
+}
+
+impl IccProfile {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 132 || data.get(36..40) != Some(b"acsp") {
+            return Err("Not an ICC profile".to_string());
+        }
+        if &data[16..20] != b"RGB " {
+            return Err(format!("Unsupported {} color space", String::from_utf8_lossy(&data[16..20]).trim()));
+        }
+        if &data[20..24] != b"XYZ " {
+            return Err("Unsupported Lab connection space".to_string());
+        }
+
+        let tag_count = be_u32(data, 128).unwrap_or(0) as usize;
+        let tag = |signature: &[u8]| {
+            (0..tag_count).find_map(|index| {
+                let entry = 132 + index * 12;
+                if data.get(entry..entry + 4)? != signature {
+                    return None;
+                }
+                let offset = be_u32(data, entry + 4)? as usize;
+                let size = be_u32(data, entry + 8)? as usize;
+                data.get(offset..offset + size)
+            })
+        };
+        let colorant = |signature: &[u8]| {
+            tag(signature).and_then(parse_xyz).ok_or_else(|| "Not a matrix/TRC profile (LUT profiles are unsupported)".to_string())
+        };
+        let curve = |signature: &[u8]| tag(signature).and_then(parse_curve).ok_or_else(|| "Missing or unsupported tone curve".to_string());
+
+        let (red, green, blue) = (colorant(b"rXYZ")?, colorant(b"gXYZ")?, colorant(b"bXYZ")?);
+        let to_xyz = [
+            [red[0], green[0], blue[0]],
+            [red[1], green[1], blue[1]],
+            [red[2], green[2], blue[2]],
+        ];
+        let curves = [curve(b"rTRC")?, curve(b"gTRC")?, curve(b"bTRC")?];
+        let description = tag(b"desc").and_then(parse_description).unwrap_or_else(|| "Unnamed profile".to_string());
+        Ok(Self { description, to_xyz, curves })
+    }
+
+    /// Whether the profile describes sRGB closely enough to leave the pixels alone.
+    pub fn is_srgb(&self) -> bool {
+        let matrix_matches = self.to_xyz.iter().flatten().zip(SRGB_TO_XYZ_D50.iter().flatten())
+            .all(|(value, srgb)| (value - srgb).abs() < SRGB_TOLERANCE);
+        let curves_match = self.curves.iter().all(|curve| {
+            [0.02, 0.2, 0.5, 0.8].iter().all(|&x| (curve.eval(x) - srgb_to_linear(x)).abs() < SRGB_TOLERANCE)
+        });
+        matrix_matches && curves_match
+    }
+
+    /// Transforms `img` from this profile to sRGB, keeping its channel layout and bit depth.
+    /// Colors outside the sRGB gamut are clipped.
+    pub fn convert_to_srgb(&self, img: DynamicImage) -> DynamicImage {
+        let matrix = multiply(&invert(&SRGB_TO_XYZ_D50), &self.to_xyz);
+        let transform = |rgb: [f64; 3]| {
+            let linear = [self.curves[0].eval(rgb[0]), self.curves[1].eval(rgb[1]), self.curves[2].eval(rgb[2])];
+            matrix.map(|row| linear_to_srgb((row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2]).clamp(0.0, 1.0)))
+        };
+        match img {
+            DynamicImage::ImageRgb8(buffer) => DynamicImage::ImageRgb8(map_rgb(buffer, transform)),
+            DynamicImage::ImageRgba8(buffer) => DynamicImage::ImageRgba8(map_rgb(buffer, transform)),
+            DynamicImage::ImageRgb16(buffer) => DynamicImage::ImageRgb16(map_rgb(buffer, transform)),
+            DynamicImage::ImageRgba16(buffer) => DynamicImage::ImageRgba16(map_rgb(buffer, transform)),
+            DynamicImage::ImageRgb32F(buffer) => DynamicImage::ImageRgb32F(map_rgb(buffer, transform)),
+            DynamicImage::ImageRgba32F(buffer) => DynamicImage::ImageRgba32F(map_rgb(buffer, transform)),
+            // Gray images carry gray profiles, which `parse` rejects
+            other => other,
+        }
+    }
+}
+
+/// Applies `transform` to the color channels of every pixel, leaving alpha untouched.
+fn map_rgb<P, S>(mut buffer: ImageBuffer<P, Vec<S>>, transform: impl Fn([f64; 3]) -> [f64; 3]) -> ImageBuffer<P, Vec<S>>
+where
+    P: Pixel<Subpixel = S>,
+    S: Primitive,
+{
+    let max = S::DEFAULT_MAX_VALUE.to_f64().unwrap_or(1.0);
+    for pixel in buffer.pixels_mut() {
+        let channels = pixel.channels_mut();
+        let rgb = [0, 1, 2].map(|channel| channels[channel].to_f64().unwrap_or(0.0) / max);
+        for (channel, value) in transform(rgb).into_iter().enumerate() {
+            channels[channel] = S::from((value * max).round()).unwrap_or(S::DEFAULT_MAX_VALUE);
+        }
+    }
+    buffer
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn s15_fixed16(data: &[u8], offset: usize) -> Option<f64> {
+    Some(be_u32(data, offset)? as i32 as f64 / 65536.0)
+}
+
+fn parse_xyz(tag: &[u8]) -> Option<[f64; 3]> {
+    if tag.get(0..4)? != b"XYZ " {
+        return None;
+    }
+    Some([s15_fixed16(tag, 8)?, s15_fixed16(tag, 12)?, s15_fixed16(tag, 16)?])
+}
+
+fn parse_curve(tag: &[u8]) -> Option<ToneCurve> {
+    match tag.get(0..4)? {
+        b"curv" => match be_u32(tag, 8)? {
+            0 => Some(ToneCurve::Gamma(1.0)),
+            1 => Some(ToneCurve::Gamma(u16::from_be_bytes([*tag.get(12)?, *tag.get(13)?]) as f64 / 256.0)),
+            count => {
+                let table = (0..count as usize)
+                    .map(|index| Some(u16::from_be_bytes([*tag.get(12 + index * 2)?, *tag.get(13 + index * 2)?]) as f64 / 65535.0))
+                    .collect::<Option<Vec<f64>>>()?;
+                Some(ToneCurve::Table(table))
+            }
+        },
+        b"para" => {
+            let function = u16::from_be_bytes([*tag.get(8)?, *tag.get(9)?]);
+            let parameter_count = match function {
+                0 => 1,
+                1 => 3,
+                2 => 4,
+                3 => 5,
+                4 => 7,
+                _ => return None,
+            };
+            let parameters = (0..parameter_count).map(|index| s15_fixed16(tag, 12 + index * 4)).collect::<Option<Vec<f64>>>()?;
+            let g = parameters[0];
+            // Types 1 and 2 start the curve at `-b/a`, which is undefined for a zero `a`
+            if matches!(function, 1 | 2) && parameters[1] == 0.0 {
+                return None;
+            }
+            // Every type is a special case of type 4, `(a*x + b)^g + e` from `d` up, `c*x + f` below
+            let (a, b, c, d, e, f) = match function {
+                0 => (1.0, 0.0, 0.0, 0.0, 0.0, 0.0),
+                1 => (parameters[1], parameters[2], 0.0, -parameters[2] / parameters[1], 0.0, 0.0),
+                2 => (parameters[1], parameters[2], 0.0, -parameters[2] / parameters[1], parameters[3], parameters[3]),
+                3 => (parameters[1], parameters[2], parameters[3], parameters[4], 0.0, 0.0),
+                _ => (parameters[1], parameters[2], parameters[3], parameters[4], parameters[5], parameters[6]),
+            };
+            Some(ToneCurve::Parametric { g, a, b, c, d, e, f })
+        }
+        _ => None,
+    }
+}
+
+/// Text of a v2 `desc` or v4 `mluc` description tag (first record).
+fn parse_description(tag: &[u8]) -> Option<String> {
+    let text = match tag.get(0..4)? {
+        b"desc" => {
+            let length = be_u32(tag, 8)? as usize;
+            String::from_utf8_lossy(tag.get(12..12 + length)?).to_string()
+        }
+        b"mluc" => {
+            let length = be_u32(tag, 20)? as usize;
+            let offset = be_u32(tag, 24)? as usize;
+            let units: Vec<u16> = tag.get(offset..offset + length)?.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+fn multiply(left: &[[f64; 3]; 3], right: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut product = [[0.0; 3]; 3];
+    for (row, product_row) in product.iter_mut().enumerate() {
+        for (column, value) in product_row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| left[row][k] * right[k][column]).sum();
+        }
+    }
+    product
+}
+
+fn invert(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let determinant = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2) + m[0][2] * cofactor(1, 2, 0, 1);
+    [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ]
+    .map(|row| row.map(|value| value / determinant))
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    /// Display P3 and Adobe RGB (1998) colorants adapted to D50, as in the profiles macOS and Adobe ship.
+    const DISPLAY_P3: [[f64; 3]; 3] = [[0.515_121, 0.241_196, -0.001_053], [0.291_977, 0.692_245, 0.041_885], [0.157_104, 0.066_574, 0.784_073]];
+    const ADOBE_RGB: [[f64; 3]; 3] = [[0.609_741, 0.311_111, 0.019_470], [0.205_276, 0.625_671, 0.060_867], [0.149_185, 0.063_217, 0.744_568]];
+
+    fn s15_fixed16_bytes(value: f64) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+
+    /// `para` tag with the sRGB tone curve (type 3).
+    fn srgb_curve() -> Vec<u8> {
+        parametric_curve(3, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045])
+    }
+
+    fn parametric_curve(function: u16, parameters: &[f64]) -> Vec<u8> {
+        let mut tag = b"para\0\0\0\0".to_vec();
+        tag.extend_from_slice(&function.to_be_bytes());
+        tag.extend_from_slice(&[0, 0]);
+        for &parameter in parameters {
+            tag.extend_from_slice(&s15_fixed16_bytes(parameter));
+        }
+        tag
+    }
+
+    /// `curv` tag holding a single u8.8 gamma.
+    fn gamma_curve(gamma: u16) -> Vec<u8> {
+        let mut tag = b"curv\0\0\0\0".to_vec();
+        tag.extend_from_slice(&1u32.to_be_bytes());
+        tag.extend_from_slice(&gamma.to_be_bytes());
+        tag
+    }
+
+    /// A minimal v2 matrix/TRC profile, `colorants` holding the red, green and blue XYZ values.
+    fn profile(colorants: [[f64; 3]; 3], curve: Vec<u8>, description: &str) -> Vec<u8> {
+        let mut desc = b"desc\0\0\0\0".to_vec();
+        desc.extend_from_slice(&(description.len() as u32 + 1).to_be_bytes());
+        desc.extend_from_slice(description.as_bytes());
+        desc.push(0);
+        let mut tags: Vec<(&[u8; 4], Vec<u8>)> = vec![(b"desc", desc)];
+        for (signature, xyz) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().zip(colorants) {
+            let mut tag = b"XYZ \0\0\0\0".to_vec();
+            xyz.iter().for_each(|&value| tag.extend_from_slice(&s15_fixed16_bytes(value)));
+            tags.push((signature, tag));
+        }
+        for signature in [b"rTRC", b"gTRC", b"bTRC"] {
+            tags.push((signature, curve.clone()));
+        }
+
+        let mut data = vec![0u8; 128];
+        data[16..20].copy_from_slice(b"RGB ");
+        data[20..24].copy_from_slice(b"XYZ ");
+        data[36..40].copy_from_slice(b"acsp");
+        data.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+        let mut offset = 132 + tags.len() * 12;
+        let mut payloads = Vec::new();
+        for (signature, tag) in &tags {
+            data.extend_from_slice(*signature);
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+            offset += tag.len();
+            payloads.extend_from_slice(tag);
+        }
+        data.extend_from_slice(&payloads);
+        data
+    }
+
+    /// A Display P3 profile with the sRGB tone curve, for tests of tagged sources elsewhere.
+    pub(crate) fn display_p3() -> Vec<u8> {
+        profile(DISPLAY_P3, srgb_curve(), "Display P3")
+    }
+
+    fn convert(profile: &IccProfile, rgb: [u8; 3]) -> [u8; 3] {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, image::Rgb(rgb)));
+        profile.convert_to_srgb(img).into_rgb8().get_pixel(0, 0).0
+    }
+
+    fn assert_close(actual: [u8; 3], expected: [u8; 3]) {
+        assert!(actual.iter().zip(expected).all(|(&a, e)| a.abs_diff(e) <= 1), "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn parses_profile_description_and_recognizes_srgb() {
+        let colorants = [0, 1, 2].map(|channel| SRGB_TO_XYZ_D50.map(|row| row[channel]));
+        let srgb = IccProfile::parse(&profile(colorants, srgb_curve(), "sRGB IEC61966-2.1")).unwrap();
+        assert_eq!(srgb.description, "sRGB IEC61966-2.1");
+        assert!(srgb.is_srgb());
+        assert!(!IccProfile::parse(&profile(DISPLAY_P3, srgb_curve(), "Display P3")).unwrap().is_srgb());
+    }
+
+    #[test]
+    fn converts_display_p3_to_srgb() {
+        let p3 = IccProfile::parse(&profile(DISPLAY_P3, srgb_curve(), "Display P3")).unwrap();
+        // Expected values from the D65 P3 -> sRGB matrix of the published primaries
+        assert_close(convert(&p3, [204, 102, 51]), [219, 94, 31]);
+        // Out of gamut for sRGB, so red clips to 0
+        assert_close(convert(&p3, [51, 153, 230]), [0, 156, 236]);
+        assert_close(convert(&p3, [128, 128, 128]), [128, 128, 128]);
+        assert_eq!(convert(&p3, [255, 255, 255]), [255, 255, 255]);
+        assert_eq!(convert(&p3, [255, 0, 0]), [255, 0, 0]);
+    }
+
+    #[test]
+    fn converts_adobe_rgb_to_srgb() {
+        // Adobe RGB's gamma is 563/256
+        let adobe = IccProfile::parse(&profile(ADOBE_RGB, gamma_curve(0x0233), "Adobe RGB (1998)")).unwrap();
+        assert_eq!(adobe.description, "Adobe RGB (1998)");
+        assert_close(convert(&adobe, [204, 102, 51]), [231, 102, 43]);
+        assert_close(convert(&adobe, [51, 153, 230]), [0, 154, 233]);
+        assert_close(convert(&adobe, [128, 128, 128]), [129, 129, 129]);
+        assert_eq!(convert(&adobe, [255, 255, 255]), [255, 255, 255]);
+    }
+
+    #[test]
+    fn rejects_parametric_curves_with_zero_slope() {
+        for (function, parameters) in [(1, vec![2.2, 0.0, 0.1]), (2, vec![2.2, 0.0, 0.1, 0.05])] {
+            let data = profile(DISPLAY_P3, parametric_curve(function, &parameters), "Broken");
+            assert!(IccProfile::parse(&data).is_err(), "type {} with a = 0 was accepted", function);
+        }
+        // Type 1 with a usable slope still parses
+        assert!(IccProfile::parse(&profile(DISPLAY_P3, parametric_curve(1, &[2.2, 1.0, 0.0]), "Gamma 2.2")).is_ok());
+    }
+
+    #[test]
+    fn rejects_non_rgb_and_truncated_profiles() {
+        let mut gray = profile(DISPLAY_P3, srgb_curve(), "Gray");
+        gray[16..20].copy_from_slice(b"GRAY");
+        assert!(IccProfile::parse(&gray).is_err());
+        assert!(IccProfile::parse(&profile(DISPLAY_P3, srgb_curve(), "Cut")[..200]).is_err());
+        assert!(IccProfile::parse(b"not a profile").is_err());
+    }
+}
@@ -0,0 +1,497 @@
+// conversion.rs
+use crate::color::ColorManagement;
+use crate::encoders::{builtin_encoders, EncodeReport, EncodingMode, ImageEncoder};
+use crate::image_processing;
+use crate::metadata::{MetadataPolicy, Orientation};
+use crate::metrics::{FidelityMetrics, QualityMetric};
+use crate::pixel_format::AlphaHandling;
+use crate::resize::{CropRect, ResizeFilter, ResizeMode};
+use parking_lot::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+#[derive(Clone)]
+pub enum ConversionUpdate {
+    Progress(usize, usize),  // (completed, total)
+    ImageProcessed(usize, Option<u64>, Option<f32>),  // (index, compressed_size, compression_rate)
+    Completed,
+    StatusUpdate(usize, String, Option<String>),  // (index, status, error_message)
+    ResultsUpdate(f64, f64),  // (total_original, total_compressed)
+    EncodingUsed(usize, EncodingMode),  // (index, mode)
+    AutoDecision(usize, EncodingMode, String),  // (index, chosen mode, reason)
+    QualityUsed(usize, f32),  // (index, quality)
+    MetricMeasured(usize, QualityMetric, f64),  // (index, metric, value)
+    FidelityMeasured(usize, FidelityMetrics),  // (index, metrics)
+    FormatProcessed(usize, FormatOutput),  // (index, result for one output format)
+    SrcsetGenerated(usize, String, String),  // (index, format, srcset)
+    Dimensions(usize, (u32, u32), (u32, u32)),  // (index, original, resized)
+    FilterUsed(usize, ResizeFilter, bool),  // (index, filter, linear light)
+    Cropped(usize, CropRect),  // (index, source window kept)
+    Oriented(usize, Orientation),  // (index, EXIF orientation corrected)
+    MetadataScrubbed(usize, Vec<String>),  // (index, sensitive fields removed)
+    ColorConverted(usize, String),  // (index, conversion, e.g. "Display P3 -> sRGB")
+    PixelsConverted(usize, Vec<String>),  // (index, bit depth, channel and alpha conversions)
+    SourceFormat(usize, String),  // (index, format detected from the file contents, e.g. "WebP")
+    Animated(usize, usize, u64),  // (index, frame count, duration of one loop in ms)
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageDetail {
+    pub name: String,
+    pub original_size: u64,
+    pub compressed_size: Option<u64>,
+    pub compression_rate: Option<f32>,
+    pub status: String,
+    pub error_message: Option<String>,
+    /// Input format, detected from the file contents rather than its extension.
+    pub source_format: Option<String>,
+    /// Frames in an animated source; `None` for stills.
+    pub frame_count: Option<usize>,
+    /// Length of one loop of an animated source, in milliseconds.
+    pub duration_ms: Option<u64>,
+    /// EXIF orientation turned upright on load.
+    pub orientation: Option<Orientation>,
+    /// Source size in pixels (upright), once known.
+    pub original_dimensions: Option<(u32, u32)>,
+    /// Size after resizing (before any srcset variants).
+    pub output_dimensions: Option<(u32, u32)>,
+    /// Filter used when the image was actually resampled, and whether it ran in linear light.
+    pub resize_filter: Option<(ResizeFilter, bool)>,
+    /// Source window kept by the `Fill` and `SmartCrop` resize modes.
+    pub crop: Option<CropRect>,
+    pub encoding_mode: Option<EncodingMode>,
+    /// Why `EncodingMode::Auto` settled on `encoding_mode`.
+    pub mode_reason: Option<String>,
+    /// Lossy quality the output was encoded at.
+    pub quality: Option<f32>,
+    /// Metric measured by `EncodingMode::TargetQuality` on the chosen output.
+    pub measured_metric: Option<(QualityMetric, f64)>,
+    /// Output-vs-source fidelity, when `ConversionOptions::compute_metrics` is set.
+    pub fidelity: Option<FidelityMetrics>,
+    /// Name of the encoder that wrote the output.
+    pub output_format: Option<String>,
+    /// ICC conversion applied, e.g. "Display P3 -> sRGB".
+    pub color_conversion: Option<String>,
+    /// Bit depth, channel and alpha conversions made to reach 8-bit RGB(A), e.g. "Rgb16 dithered to 8-bit".
+    pub pixel_conversion: Vec<String>,
+    /// Sensitive metadata found and removed under `MetadataPolicy::ScrubSensitive`, e.g. "GPS".
+    pub scrubbed: Vec<String>,
+    /// One entry per requested format (and srcset width); the fields above mirror the first.
+    pub outputs: Vec<FormatOutput>,
+    /// `(format, srcset)` pairs when `ConversionOptions::srcset_widths` is set.
+    pub srcsets: Vec<(String, String)>,
+}
+
+impl ImageDetail {
+    pub fn new(name: String, original_size: u64) -> Self {
+        Self {
+            name,
+            original_size,
+            compressed_size: None,
+            compression_rate: None,
+            status: "Load successful".to_string(),
+            error_message: None,
+            source_format: None,
+            frame_count: None,
+            duration_ms: None,
+            orientation: None,
+            original_dimensions: None,
+            output_dimensions: None,
+            resize_filter: None,
+            crop: None,
+            encoding_mode: None,
+            mode_reason: None,
+            quality: None,
+            measured_metric: None,
+            fidelity: None,
+            output_format: None,
+            color_conversion: None,
+            pixel_conversion: Vec::new(),
+            scrubbed: Vec::new(),
+            outputs: Vec::new(),
+            srcsets: Vec::new(),
+        }
+    }
+}
+
+/// Result of writing one image in one output format.
+#[derive(Clone, Debug)]
+pub struct FormatOutput {
+    pub format: String,
+    /// Srcset width of this variant, `None` for the single-output case.
+    pub width: Option<u32>,
+    /// Pixel size of the written image.
+    pub dimensions: Option<(u32, u32)>,
+    pub compressed_size: Option<u64>,
+    pub compression_rate: Option<f32>,
+    pub status: String,
+    pub error_message: Option<String>,
+    pub encoding_mode: Option<EncodingMode>,
+    pub mode_reason: Option<String>,
+    pub quality: Option<f32>,
+    pub measured_metric: Option<(QualityMetric, f64)>,
+    pub fidelity: Option<FidelityMetrics>,
+}
+
+impl FormatOutput {
+    pub(crate) fn new(
+        format: &str,
+        width: Option<u32>,
+        original_size: u64,
+        result: Result<(u64, EncodeReport, Option<FidelityMetrics>), String>,
+    ) -> Self {
+        let mut output = Self {
+            format: format.to_string(),
+            width,
+            dimensions: None,
+            compressed_size: None,
+            compression_rate: None,
+            status: "Conversion failed".to_string(),
+            error_message: None,
+            encoding_mode: None,
+            mode_reason: None,
+            quality: None,
+            measured_metric: None,
+            fidelity: None,
+        };
+        match result {
+            Ok((compressed_size, report, fidelity)) => {
+                output.compressed_size = Some(compressed_size);
+                output.compression_rate = Some(1.0 - (compressed_size as f32 / original_size as f32));
+                output.encoding_mode = report.mode;
+                output.mode_reason = report.reason;
+                output.quality = report.quality;
+                output.measured_metric = report.metric;
+                output.fidelity = fidelity;
+                match report.warning {
+                    Some(warning) => {
+                        output.status = warning.status.to_string();
+                        output.error_message = Some(warning.message);
+                    }
+                    None => output.status = "Conversion successful".to_string(),
+                }
+            }
+            Err(error_msg) => output.error_message = Some(error_msg),
+        }
+        output
+    }
+
+    /// Format name with the srcset width, e.g. "WebP 640w".
+    pub fn label(&self) -> String {
+        match self.width {
+            Some(width) => format!("{} {}w", self.format, width),
+            None => self.format.clone(),
+        }
+    }
+}
+
+/// Settings applied to every image of a job.
+#[derive(Clone, Debug)]
+pub struct ConversionOptions {
+    /// Rotate/flip images upright per their EXIF Orientation tag before resizing.
+    pub auto_orient: bool,
+    pub resize_enabled: bool,
+    pub resize_mode: ResizeMode,
+    /// Box for `Exact`, `Fit` and `Fill`; `Width`/`Height` use only one of them.
+    pub width: u32,
+    pub height: u32,
+    /// Longest edge for `ResizeMode::MaxEdge`.
+    pub max_edge: u32,
+    /// Scale for `ResizeMode::Percentage`, e.g. 50.0 for half size.
+    pub scale_percent: f32,
+    /// Keep images that are already smaller than the target at their size.
+    pub never_upscale: bool,
+    pub resize_filter: ResizeFilter,
+    /// Downscale in linear light instead of gamma-encoded sRGB, which keeps fine detail from darkening.
+    pub linear_light: bool,
+    /// Resize transparent images with premultiplied alpha, which keeps dark halos off their edges.
+    pub premultiplied_alpha: bool,
+    pub alpha: AlphaHandling,
+    /// Color transparent images are flattened onto with `AlphaHandling::Flatten`.
+    pub background: [u8; 3],
+    pub rename_enabled: bool,
+    pub output_filename: String,
+    /// Names of the registered encoders to write with, matched case-insensitively.
+    /// Each input is decoded and resized once, then written in every format.
+    pub encoders: Vec<String>,
+    /// Write one variant per width (after any resize), e.g. `[320, 640, 1280]`; empty writes a single output.
+    /// Widths larger than the image are skipped.
+    pub srcset_widths: Vec<u32>,
+    /// Decode every output and measure it against the source; costs an extra decode per image.
+    pub compute_metrics: bool,
+    /// Source EXIF, XMP and ICC carried into outputs whose encoder can embed them.
+    pub metadata: MetadataPolicy,
+    /// What to do with embedded ICC profiles; a preserved profile is embedded even when `metadata` strips the rest.
+    pub color_management: ColorManagement,
+    /// Playback speed of animated outputs relative to the source, e.g. 2.0 for twice as fast.
+    pub animation_speed: f32,
+    /// Times animated outputs play (0 for forever); `None` keeps the source's count.
+    pub loop_count: Option<u16>,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> Self {
+        Self {
+            auto_orient: true,
+            resize_enabled: false,
+            resize_mode: ResizeMode::Fit,
+            width: 800,
+            height: 600,
+            max_edge: 1920,
+            scale_percent: 50.0,
+            never_upscale: false,
+            resize_filter: ResizeFilter::Lanczos3,
+            linear_light: false,
+            premultiplied_alpha: true,
+            alpha: AlphaHandling::Keep,
+            background: [255, 255, 255],
+            rename_enabled: false,
+            output_filename: String::from("output"),
+            encoders: vec![String::from("WebP")],
+            srcset_widths: Vec::new(),
+            compute_metrics: false,
+            metadata: MetadataPolicy::StripAll,
+            color_management: ColorManagement::ConvertToSrgb,
+            animation_speed: 1.0,
+            loop_count: None,
+        }
+    }
+}
+
+impl ConversionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn auto_orient(mut self, auto_orient: bool) -> Self {
+        self.auto_orient = auto_orient;
+        self
+    }
+
+    /// Resize every image to the `width` x `height` box, as interpreted by `resize_mode` (fit by default).
+    pub fn resize(mut self, width: u32, height: u32) -> Self {
+        self.resize_enabled = true;
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn resize_mode(mut self, resize_mode: ResizeMode) -> Self {
+        self.resize_mode = resize_mode;
+        self
+    }
+
+    /// Scale every image so its longest edge is `max_edge` pixels.
+    pub fn max_edge(mut self, max_edge: u32) -> Self {
+        self.resize_enabled = true;
+        self.resize_mode = ResizeMode::MaxEdge;
+        self.max_edge = max_edge;
+        self
+    }
+
+    /// Scale every image by `percent` (100.0 keeps the size).
+    pub fn scale(mut self, percent: f32) -> Self {
+        self.resize_enabled = true;
+        self.resize_mode = ResizeMode::Percentage;
+        self.scale_percent = percent;
+        self
+    }
+
+    pub fn never_upscale(mut self, never_upscale: bool) -> Self {
+        self.never_upscale = never_upscale;
+        self
+    }
+
+    pub fn resize_filter(mut self, resize_filter: ResizeFilter) -> Self {
+        self.resize_filter = resize_filter;
+        self
+    }
+
+    pub fn linear_light(mut self, linear_light: bool) -> Self {
+        self.linear_light = linear_light;
+        self
+    }
+
+    pub fn premultiplied_alpha(mut self, premultiplied_alpha: bool) -> Self {
+        self.premultiplied_alpha = premultiplied_alpha;
+        self
+    }
+
+    pub fn alpha(mut self, alpha: AlphaHandling) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    /// Flatten transparent images onto `background` (RGB) before encoding.
+    pub fn flatten_onto(mut self, background: [u8; 3]) -> Self {
+        self.alpha = AlphaHandling::Flatten;
+        self.background = background;
+        self
+    }
+
+    /// Name the output files `<output_filename>.<ext>` instead of reusing the input stem.
+    /// Jobs with several inputs number them `<output_filename>-1.<ext>`, `<output_filename>-2.<ext>`, ...
+    pub fn rename(mut self, output_filename: impl Into<String>) -> Self {
+        self.rename_enabled = true;
+        self.output_filename = output_filename.into();
+        self
+    }
+
+    /// Write with the registered encoder called `encoder` only.
+    pub fn encoder(mut self, encoder: impl Into<String>) -> Self {
+        self.encoders = vec![encoder.into()];
+        self
+    }
+
+    /// Write every image once per named encoder, e.g. `["AVIF", "WebP", "JPEG"]` for a `<picture>` element.
+    pub fn encoders<I, S>(mut self, encoders: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.encoders = encoders.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Write `photo-<width>w.<ext>` variants for each width instead of a single output.
+    pub fn srcset_widths(mut self, widths: Vec<u32>) -> Self {
+        self.srcset_widths = widths;
+        self
+    }
+
+    /// Report PSNR, SSIM and MS-SSIM for every converted image.
+    pub fn compute_metrics(mut self, compute_metrics: bool) -> Self {
+        self.compute_metrics = compute_metrics;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: MetadataPolicy) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn color_management(mut self, color_management: ColorManagement) -> Self {
+        self.color_management = color_management;
+        self
+    }
+
+    /// Retime animated outputs to play `speed` times as fast as the source.
+    pub fn animation_speed(mut self, speed: f32) -> Self {
+        self.animation_speed = speed;
+        self
+    }
+
+    /// Play animated outputs `loop_count` times, 0 for forever.
+    pub fn loop_count(mut self, loop_count: u16) -> Self {
+        self.loop_count = Some(loop_count);
+        self
+    }
+}
+
+/// A set of input files, where to write them and how to convert them.
+#[derive(Clone, Debug)]
+pub struct ConversionJob {
+    pub input_files: Vec<PathBuf>,
+    pub output_directory: PathBuf,
+    /// Folders the inputs were collected from; outputs of an input below one keep its subfolder.
+    pub input_roots: Vec<PathBuf>,
+    pub options: ConversionOptions,
+}
+
+impl ConversionJob {
+    pub fn new(input_files: Vec<PathBuf>, output_directory: impl Into<PathBuf>) -> Self {
+        Self {
+            input_files,
+            output_directory: output_directory.into(),
+            input_roots: Vec::new(),
+            options: ConversionOptions::default(),
+        }
+    }
+
+    pub fn options(mut self, options: ConversionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Mirror the folder structure below `root` in the output directory, e.g. `root/a/img.jpg` to `a/img.webp`.
+    pub fn input_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.input_roots.push(root.into());
+        self
+    }
+
+    /// Where the outputs of `input_path` go: the output directory, plus the input's subfolder below
+    /// the closest of `input_roots` that holds it.
+    pub fn output_folder(&self, input_path: &Path) -> PathBuf {
+        let parent = input_path.parent().unwrap_or(Path::new(""));
+        let subfolder = self.input_roots.iter()
+            .filter_map(|root| parent.strip_prefix(root).ok())
+            .min_by_key(|relative| relative.components().count());
+        match subfolder {
+            Some(subfolder) => self.output_directory.join(subfolder),
+            None => self.output_directory.clone(),
+        }
+    }
+}
+
+/// Runs conversion jobs, reporting progress over an optional channel.
+pub struct Converter {
+    log_messages: Arc<Mutex<Vec<String>>>,
+    sender: Option<Sender<ConversionUpdate>>,
+    encoders: Vec<Box<dyn ImageEncoder>>,
+}
+
+impl Default for Converter {
+    fn default() -> Self {
+        Self {
+            log_messages: Arc::new(Mutex::new(Vec::new())),
+            sender: None,
+            encoders: builtin_encoders(),
+        }
+    }
+}
+
+impl Converter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append log lines to `log_messages` instead of a private buffer.
+    pub fn log_messages(mut self, log_messages: Arc<Mutex<Vec<String>>>) -> Self {
+        self.log_messages = log_messages;
+        self
+    }
+
+    /// Send a `ConversionUpdate` for every step of the run.
+    pub fn updates(mut self, sender: Sender<ConversionUpdate>) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    /// Replace the registered backends (the built-in ones by default).
+    pub fn encoders(mut self, encoders: Vec<Box<dyn ImageEncoder>>) -> Self {
+        self.encoders = encoders;
+        self
+    }
+
+    /// Add a backend, replacing any registered under the same name.
+    pub fn register_encoder(mut self, encoder: Box<dyn ImageEncoder>) -> Self {
+        self.encoders.retain(|registered| !registered.name().eq_ignore_ascii_case(encoder.name()));
+        self.encoders.push(encoder);
+        self
+    }
+
+    pub fn registered_encoders(&self) -> &[Box<dyn ImageEncoder>] {
+        &self.encoders
+    }
+
+    /// Converts every file of `job`, returning one `ImageDetail` per input in order.
+    pub fn run(&self, job: &ConversionJob) -> Vec<ImageDetail> {
+        image_processing::convert_images(job, &self.encoders, self.log_messages.clone(), self.sender.as_ref())
+    }
+
+    /// Writes every frame of each input as `<name>-frame-001.png` and so on, instead of converting it.
+    pub fn extract_frames(&self, job: &ConversionJob) -> Vec<ImageDetail> {
+        image_processing::extract_frames(job, self.log_messages.clone(), self.sender.as_ref())
+    }
+}
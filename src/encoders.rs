@@ -0,0 +1,208 @@
+// encoders.rs
+pub mod avif;
+pub mod jpeg;
+pub mod webp;
+
+pub use self::avif::{AvifEncodeSettings, AvifEncoder};
+pub use self::jpeg::JpegEncoder;
+pub use self::webp::{EncodingMode, WebpEncodeSettings, WebpEncoder};
+
+use crate::animation::Animation;
+use crate::metadata::Metadata;
+use crate::metrics::QualityMetric;
+use image::{DynamicImage, ImageError};
+use std::borrow::Cow;
+
+/// An output codec the converter can write.
+///
+/// Backends own their settings and describe them through `options_schema`, so front ends can
+/// build controls without knowing the concrete type.
+pub trait ImageEncoder: Send + Sync {
+    /// Display name, also used to select the backend (e.g. "WebP").
+    fn name(&self) -> &str;
+
+    /// Extension of the written files, without the dot.
+    fn extension(&self) -> &str;
+
+    /// Settings currently worth showing; may depend on other values, such as the WebP mode.
+    fn options_schema(&self) -> Vec<EncoderOption> {
+        Vec::new()
+    }
+
+    fn option(&self, _key: &str) -> Option<OptionValue> {
+        None
+    }
+
+    /// Unknown keys and values of the wrong kind are ignored.
+    fn set_option(&mut self, _key: &str, _value: OptionValue) {}
+
+    /// Restores every setting to its default.
+    fn reset_options(&mut self) {}
+
+    fn encode(&self, img: &DynamicImage) -> Result<Vec<u8>, ImageError>;
+
+    /// Encodes and reports what was chosen along the way; backends that search or pick modes override this.
+    fn encode_with_report(&self, img: &DynamicImage) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+        self.encode(img).map(|data| (data, EncodeReport::default()))
+    }
+
+    /// Decodes this backend's output back to pixels, for fidelity measurements.
+    fn decode(&self, _data: &[u8]) -> Result<DynamicImage, ImageError> {
+        Err(unsupported_error(self.name(), "decoding"))
+    }
+
+    /// Decodes every frame of this backend's animated output, for fidelity measurements.
+    fn decode_animation(&self, _data: &[u8]) -> Result<Animation, ImageError> {
+        Err(unsupported_error(self.name(), "animation decoding"))
+    }
+
+    /// The pixels this backend actually encodes for `img`, which fidelity is measured against;
+    /// backends without alpha return `img` flattened the way they flatten it.
+    fn fidelity_reference<'a>(&self, img: &'a DynamicImage) -> Cow<'a, DynamicImage> {
+        Cow::Borrowed(img)
+    }
+
+    /// Adds EXIF, XMP and ICC payloads to this backend's encoded `data`.
+    fn embed_metadata(&self, _data: &[u8], _metadata: &Metadata) -> Result<Vec<u8>, ImageError> {
+        Err(unsupported_error(self.name(), "metadata"))
+    }
+
+    /// A copy whose size targets leave room for `metadata`, which `embed_metadata` adds after encoding.
+    fn leaving_room_for(&self, _metadata: &Metadata) -> Box<dyn ImageEncoder> {
+        self.box_clone()
+    }
+
+    /// Encodes every frame of `animation` into one animated file.
+    fn encode_animation(&self, _animation: &Animation) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+        Err(unsupported_error(self.name(), "animation"))
+    }
+
+    fn box_clone(&self) -> Box<dyn ImageEncoder>;
+}
+
+impl Clone for Box<dyn ImageEncoder> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// The backends shipped with the crate, WebP first.
+pub fn builtin_encoders() -> Vec<Box<dyn ImageEncoder>> {
+    vec![Box::new(WebpEncoder::default()), Box::new(AvifEncoder::default()), Box::new(JpegEncoder::default())]
+}
+
+/// What an encoder settled on for one image.
+#[derive(Clone, Debug, Default)]
+pub struct EncodeReport {
+    /// WebP bitstream mode; `None` for backends without modes.
+    pub mode: Option<EncodingMode>,
+    /// Lossy quality the output was encoded at, `None` for lossless bitstreams.
+    pub quality: Option<f32>,
+    pub metric: Option<(QualityMetric, f64)>,
+    /// Why `EncodingMode::Auto` settled on `mode`.
+    pub reason: Option<String>,
+    pub warning: Option<EncodeWarning>,
+}
+
+impl EncodeReport {
+    /// Records a warning, appending its message to any earlier one, whose status is kept.
+    pub(crate) fn warn(&mut self, status: &'static str, message: String) {
+        match &mut self.warning {
+            Some(warning) => warning.message = format!("{}; {}", warning.message, message),
+            None => self.warning = Some(EncodeWarning { status, message }),
+        }
+    }
+}
+
+/// Something the output falls short of, such as a target the encoder could not meet; the output is still written.
+#[derive(Clone, Debug)]
+pub struct EncodeWarning {
+    /// Shown as the image's status, e.g. "Over target size".
+    pub status: &'static str,
+    pub message: String,
+}
+
+/// Describes one setting exposed through `ImageEncoder::option` and `ImageEncoder::set_option`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncoderOption {
+    pub key: &'static str,
+    pub label: String,
+    pub kind: OptionKind,
+    /// Rarely-touched tuning, which front ends may tuck away.
+    pub advanced: bool,
+}
+
+impl EncoderOption {
+    pub fn new(key: &'static str, label: impl Into<String>, kind: OptionKind) -> Self {
+        Self { key, label: label.into(), kind, advanced: false }
+    }
+
+    pub fn advanced(mut self) -> Self {
+        self.advanced = true;
+        self
+    }
+}
+
+/// Type and range of an `EncoderOption`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionKind {
+    Float { min: f64, max: f64 },
+    Int { min: i64, max: i64 },
+    Bool,
+    /// One of a fixed list of labels, selected by index.
+    Choice(Vec<String>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptionValue {
+    Float(f64),
+    Int(i64),
+    Bool(bool),
+    Choice(usize),
+}
+
+impl OptionValue {
+    pub fn as_f64(self) -> Option<f64> {
+        match self {
+            OptionValue::Float(value) => Some(value),
+            OptionValue::Int(value) => Some(value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(self) -> Option<i64> {
+        match self {
+            OptionValue::Int(value) => Some(value),
+            OptionValue::Float(value) => Some(value.round() as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(self) -> Option<bool> {
+        match self {
+            OptionValue::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_choice(self) -> Option<usize> {
+        match self {
+            OptionValue::Choice(index) => Some(index),
+            _ => None,
+        }
+    }
+}
+
+fn unsupported_error(name: &str, feature: &str) -> ImageError {
+    ImageError::Unsupported(image::error::UnsupportedError::from_format_and_kind(
+        image::error::ImageFormatHint::Name(name.to_string()),
+        image::error::UnsupportedErrorKind::GenericFeature(format!("{} {}", name, feature)),
+    ))
+}
+
+pub(crate) fn encoding_error(format: image::ImageFormat, message: impl Into<String>) -> ImageError {
+    ImageError::Encoding(image::error::EncodingError::new(
+        image::error::ImageFormatHint::Exact(format),
+        message.into(),
+    ))
+}
@@ -0,0 +1,100 @@
+// avif.rs
+use super::{encoding_error, EncodeReport, EncoderOption, ImageEncoder, OptionKind, OptionValue};
+use image::{DynamicImage, ImageError};
+
+/// Settings for the rav1e-based AVIF encoder.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AvifEncodeSettings {
+    /// Color quality, 1-100.
+    pub quality: f32,
+    /// Alpha channel quality, 1-100.
+    pub alpha_quality: f32,
+    /// Encoder speed, 1 (slowest, smallest) to 10 (fastest).
+    pub speed: u8,
+}
+
+impl Default for AvifEncodeSettings {
+    fn default() -> Self {
+        Self {
+            quality: 70.0,
+            alpha_quality: 80.0,
+            speed: 6,
+        }
+    }
+}
+
+/// ravif backend.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AvifEncoder {
+    pub settings: AvifEncodeSettings,
+}
+
+impl AvifEncoder {
+    pub fn new(settings: AvifEncodeSettings) -> Self {
+        Self { settings }
+    }
+}
+
+impl ImageEncoder for AvifEncoder {
+    fn name(&self) -> &str {
+        "AVIF"
+    }
+
+    fn extension(&self) -> &str {
+        "avif"
+    }
+
+    fn options_schema(&self) -> Vec<EncoderOption> {
+        vec![
+            EncoderOption::new("quality", "Quality", OptionKind::Float { min: 1.0, max: 100.0 }),
+            EncoderOption::new("alpha_quality", "Alpha Quality", OptionKind::Float { min: 1.0, max: 100.0 }),
+            EncoderOption::new("speed", "Speed", OptionKind::Int { min: 1, max: 10 }),
+        ]
+    }
+
+    fn option(&self, key: &str) -> Option<OptionValue> {
+        match key {
+            "quality" => Some(OptionValue::Float(self.settings.quality as f64)),
+            "alpha_quality" => Some(OptionValue::Float(self.settings.alpha_quality as f64)),
+            "speed" => Some(OptionValue::Int(self.settings.speed as i64)),
+            _ => None,
+        }
+    }
+
+    fn set_option(&mut self, key: &str, value: OptionValue) {
+        let settings = &mut self.settings;
+        match key {
+            "quality" => settings.quality = value.as_f64().map_or(settings.quality, |q| q.clamp(1.0, 100.0) as f32),
+            "alpha_quality" => settings.alpha_quality = value.as_f64().map_or(settings.alpha_quality, |q| q.clamp(1.0, 100.0) as f32),
+            "speed" => settings.speed = value.as_i64().map_or(settings.speed, |speed| speed.clamp(1, 10) as u8),
+            _ => {}
+        }
+    }
+
+    fn reset_options(&mut self) {
+        self.settings = AvifEncodeSettings::default();
+    }
+
+    fn encode(&self, img: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+        let settings = &self.settings;
+        let rgba = img.to_rgba8();
+        let pixels: Vec<ravif::RGBA8> = rgba.pixels().map(|p| ravif::RGBA8::new(p[0], p[1], p[2], p[3])).collect();
+        let encoder = ravif::Encoder::new()
+            .with_quality(settings.quality.clamp(1.0, 100.0))
+            .with_alpha_quality(settings.alpha_quality.clamp(1.0, 100.0))
+            .with_speed(settings.speed.clamp(1, 10));
+        let encoded = encoder
+            .encode_rgba(ravif::Img::new(&pixels[..], rgba.width() as usize, rgba.height() as usize))
+            .map_err(|e| encoding_error(image::ImageFormat::Avif, e.to_string()))?;
+        Ok(encoded.avif_file)
+    }
+
+    fn encode_with_report(&self, img: &DynamicImage) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+        let report = EncodeReport { quality: Some(self.settings.quality), ..Default::default() };
+        self.encode(img).map(|avif_data| (avif_data, report))
+    }
+
+    fn box_clone(&self) -> Box<dyn ImageEncoder> {
+        Box::new(self.clone())
+    }
+}
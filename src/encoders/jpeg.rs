@@ -0,0 +1,213 @@
+// jpeg.rs
+use super::{encoding_error, EncodeReport, EncoderOption, ImageEncoder, OptionKind, OptionValue};
+use crate::metadata::{Metadata, JPEG_ICC_SIGNATURE, JPEG_XMP_SIGNATURE};
+use image::{DynamicImage, ImageError, Rgb, RgbImage};
+use std::borrow::Cow;
+
+/// Signature opening the JPEG APP1 segment that holds EXIF.
+const EXIF_SIGNATURE: &[u8] = b"Exif\0\0";
+/// Largest payload of one marker segment, its two length bytes aside.
+const MAX_SEGMENT_PAYLOAD: usize = 0xFFFF - 2;
+
+/// Baseline JPEG through the `image` crate, mainly as a `<picture>` fallback.
+/// Transparent areas are flattened onto white.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JpegEncoder {
+    /// Quality, 1-100.
+    pub quality: u8,
+}
+
+impl Default for JpegEncoder {
+    fn default() -> Self {
+        Self { quality: 80 }
+    }
+}
+
+impl JpegEncoder {
+    pub fn new(quality: u8) -> Self {
+        Self { quality }
+    }
+}
+
+/// Composites `img` over an opaque white background.
+fn flatten_onto_white(img: &DynamicImage) -> RgbImage {
+    let rgba = img.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let pixel = rgba.get_pixel(x, y);
+        let alpha = pixel[3] as u32;
+        let blend = |channel: u8| ((channel as u32 * alpha + 255 * (255 - alpha) + 127) / 255) as u8;
+        Rgb([blend(pixel[0]), blend(pixel[1]), blend(pixel[2])])
+    })
+}
+
+impl ImageEncoder for JpegEncoder {
+    fn name(&self) -> &str {
+        "JPEG"
+    }
+
+    fn extension(&self) -> &str {
+        "jpg"
+    }
+
+    fn options_schema(&self) -> Vec<EncoderOption> {
+        vec![EncoderOption::new("quality", "Quality", OptionKind::Int { min: 1, max: 100 })]
+    }
+
+    fn option(&self, key: &str) -> Option<OptionValue> {
+        match key {
+            "quality" => Some(OptionValue::Int(self.quality as i64)),
+            _ => None,
+        }
+    }
+
+    fn set_option(&mut self, key: &str, value: OptionValue) {
+        if key == "quality" {
+            self.quality = value.as_i64().map_or(self.quality, |quality| quality.clamp(1, 100) as u8);
+        }
+    }
+
+    fn reset_options(&mut self) {
+        *self = Self::default();
+    }
+
+    fn encode(&self, img: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+        let rgb = if img.color().has_alpha() { flatten_onto_white(img) } else { img.to_rgb8() };
+        let mut jpeg_data = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg_data, self.quality.clamp(1, 100))
+            .encode_image(&rgb)
+            .map_err(|e| encoding_error(image::ImageFormat::Jpeg, e.to_string()))?;
+        Ok(jpeg_data)
+    }
+
+    fn encode_with_report(&self, img: &DynamicImage) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+        let report = EncodeReport { quality: Some(self.quality as f32), ..Default::default() };
+        self.encode(img).map(|jpeg_data| (jpeg_data, report))
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<DynamicImage, ImageError> {
+        image::load_from_memory_with_format(data, image::ImageFormat::Jpeg)
+    }
+
+    fn fidelity_reference<'a>(&self, img: &'a DynamicImage) -> Cow<'a, DynamicImage> {
+        if img.color().has_alpha() {
+            Cow::Owned(DynamicImage::ImageRgb8(flatten_onto_white(img)))
+        } else {
+            Cow::Borrowed(img)
+        }
+    }
+
+    fn embed_metadata(&self, data: &[u8], metadata: &Metadata) -> Result<Vec<u8>, ImageError> {
+        mux_metadata(data, metadata)
+    }
+
+    fn box_clone(&self) -> Box<dyn ImageEncoder> {
+        Box::new(self.clone())
+    }
+}
+
+/// Rewrites `jpeg_data` with `metadata` in APP1 (EXIF, XMP) and APP2 (ICC) segments right after the JFIF header.
+fn mux_metadata(jpeg_data: &[u8], metadata: &Metadata) -> Result<Vec<u8>, ImageError> {
+    let invalid = |message: &str| encoding_error(image::ImageFormat::Jpeg, message);
+    if !jpeg_data.starts_with(&[0xFF, 0xD8]) {
+        return Err(invalid("Not a JPEG file"));
+    }
+
+    let mut segments = Vec::new();
+    if let Some(exif) = &metadata.exif {
+        segments.push((0xE1, [EXIF_SIGNATURE, exif].concat()));
+    }
+    if let Some(xmp) = &metadata.xmp {
+        segments.push((0xE1, [JPEG_XMP_SIGNATURE, xmp].concat()));
+    }
+    if let Some(icc) = &metadata.icc {
+        // Profiles over 64 KiB span several segments, each tagged with its 1-based sequence number and the count
+        let parts: Vec<&[u8]> = icc.chunks(MAX_SEGMENT_PAYLOAD - JPEG_ICC_SIGNATURE.len() - 2).collect();
+        let count = u8::try_from(parts.len()).map_err(|_| invalid("ICC profile too large for APP2 segments"))?;
+        for (index, part) in parts.into_iter().enumerate() {
+            segments.push((0xE2, [JPEG_ICC_SIGNATURE, &[index as u8 + 1, count], part].concat()));
+        }
+    }
+    if segments.iter().any(|(_, payload)| payload.len() > MAX_SEGMENT_PAYLOAD) {
+        return Err(invalid("EXIF or XMP too large for one APP1 segment"));
+    }
+
+    // Leading application segments: JFIF stays first, metadata of the kinds above is replaced
+    let mut jfif = Vec::new();
+    let mut others = Vec::new();
+    let mut position = 2;
+    while let (Some(&0xFF), Some(&marker @ 0xE0..=0xEF)) = (jpeg_data.get(position), jpeg_data.get(position + 1)) {
+        let length = jpeg_data.get(position + 2..position + 4).map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize);
+        let segment = jpeg_data.get(position..position + 2 + length).filter(|_| length >= 2).ok_or_else(|| invalid("Truncated APP segment"))?;
+        let payload = &segment[4..];
+        let replaced = (marker == 0xE1 && (payload.starts_with(EXIF_SIGNATURE) || payload.starts_with(JPEG_XMP_SIGNATURE)))
+            || (marker == 0xE2 && payload.starts_with(JPEG_ICC_SIGNATURE));
+        match marker {
+            0xE0 => jfif.extend_from_slice(segment),
+            _ if replaced => {}
+            _ => others.extend_from_slice(segment),
+        }
+        position += 2 + length;
+    }
+
+    let mut muxed = Vec::with_capacity(jpeg_data.len() + segments.iter().map(|(_, payload)| payload.len() + 4).sum::<usize>());
+    muxed.extend_from_slice(&[0xFF, 0xD8]);
+    muxed.extend_from_slice(&jfif);
+    for (marker, payload) in &segments {
+        muxed.extend_from_slice(&[0xFF, *marker]);
+        muxed.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        muxed.extend_from_slice(payload);
+    }
+    muxed.extend_from_slice(&others);
+    muxed.extend_from_slice(&jpeg_data[position..]);
+    Ok(muxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_jpeg() -> Vec<u8> {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(24, 16, Rgb([200, 100, 50])));
+        JpegEncoder::default().encode(&img).unwrap()
+    }
+
+    /// Markers of the segments before the scan.
+    fn markers(data: &[u8]) -> Vec<u8> {
+        let mut markers = Vec::new();
+        let mut position = 2;
+        while data[position + 1] != 0xDA {
+            markers.push(data[position + 1]);
+            position += 2 + u16::from_be_bytes([data[position + 2], data[position + 3]]) as usize;
+        }
+        markers
+    }
+
+    #[test]
+    fn embeds_metadata_after_the_jfif_header() {
+        let jpeg = sample_jpeg();
+        assert_eq!(Metadata::read(&jpeg), Metadata::default());
+        // Large enough for the profile to span three APP2 segments
+        let icc: Vec<u8> = (0..150_000u32).map(|index| index as u8).collect();
+        let metadata = Metadata { exif: Some(b"II*\0\x08\0\0\0\0\0".to_vec()), xmp: Some(b"<x:xmpmeta/>".to_vec()), icc: Some(icc) };
+        let muxed = mux_metadata(&jpeg, &metadata).unwrap();
+        assert_eq!(&markers(&muxed)[..6], &[0xE0, 0xE1, 0xE1, 0xE2, 0xE2, 0xE2]);
+        assert_eq!(Metadata::read(&muxed), metadata);
+        assert_eq!(JpegEncoder::default().decode(&muxed).unwrap().to_rgb8(), JpegEncoder::default().decode(&jpeg).unwrap().to_rgb8());
+
+        // Embedding again replaces the earlier segments
+        let smaller = Metadata { icc: Some(vec![9; 300]), ..Default::default() };
+        let remuxed = mux_metadata(&muxed, &smaller).unwrap();
+        assert_eq!(markers(&remuxed), [&[0xE0, 0xE2], &markers(&jpeg)[1..]].concat());
+        assert_eq!(Metadata::read(&remuxed), smaller);
+    }
+
+    #[test]
+    fn rejects_unfit_metadata_and_non_jpeg_data() {
+        let jpeg = sample_jpeg();
+        let oversized = Metadata { exif: Some(vec![0; MAX_SEGMENT_PAYLOAD]), ..Default::default() };
+        assert!(mux_metadata(&jpeg, &oversized).is_err());
+        let sample = Metadata { xmp: Some(b"<x:xmpmeta/>".to_vec()), ..Default::default() };
+        assert!(mux_metadata(b"RIFF\0\0\0\0WEBP", &sample).is_err());
+        assert!(mux_metadata(&jpeg[..4], &sample).is_err());
+    }
+}
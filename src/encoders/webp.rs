@@ -0,0 +1,998 @@
+// webp.rs
+use super::{encoding_error, EncodeReport, EncodeWarning, EncoderOption, ImageEncoder, OptionKind, OptionValue};
+use crate::animation::Animation;
+use crate::metadata::{riff_chunks, Metadata};
+use crate::metrics::QualityMetric;
+use image::{DynamicImage, GenericImageView, ImageError, RgbaImage};
+
+/// Which WebP bitstream to produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncodingMode {
+    Lossy,
+    Lossless,
+    /// Lossless bitstream with pixel preprocessing, see `WebpEncodeSettings::near_lossless`.
+    NearLossless,
+    /// Pick lossy or lossless per image, whichever suits the content.
+    Auto,
+    /// Lossy at the highest quality that fits `WebpEncoder::target_size`.
+    TargetSize,
+    /// Lossy at the lowest quality meeting `WebpEncoder::metric_target`.
+    TargetQuality,
+}
+
+impl EncodingMode {
+    pub const ALL: [EncodingMode; 6] = [
+        EncodingMode::Lossy,
+        EncodingMode::Lossless,
+        EncodingMode::NearLossless,
+        EncodingMode::Auto,
+        EncodingMode::TargetSize,
+        EncodingMode::TargetQuality,
+    ];
+}
+
+impl std::fmt::Display for EncodingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodingMode::Lossy => write!(f, "Lossy"),
+            EncodingMode::Lossless => write!(f, "Lossless"),
+            EncodingMode::NearLossless => write!(f, "Near-lossless"),
+            EncodingMode::Auto => write!(f, "Auto"),
+            EncodingMode::TargetSize => write!(f, "Target size"),
+            EncodingMode::TargetQuality => write!(f, "Target quality"),
+        }
+    }
+}
+
+/// Tuning knobs passed to libwebp's advanced encoder config.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebpEncodeSettings {
+    /// Speed/size tradeoff, 0 (fast) to 6 (slower, smaller).
+    pub method: u8,
+    /// Spatial noise shaping strength, 0-100.
+    pub sns_strength: u8,
+    /// Deblocking filter strength, 0-100 (0 disables filtering).
+    pub filter_strength: u8,
+    /// Deblocking filter sharpness, 0 (sharpest) to 7.
+    pub filter_sharpness: u8,
+    /// Number of segments, 1-4.
+    pub segments: u8,
+    /// Number of entropy-analysis passes, 1-10.
+    pub pass: u8,
+    /// Preprocessing filter: 0 none, 1 segment-smooth, 2 pseudo-random dithering.
+    pub preprocessing: u8,
+    /// Use the slower but more accurate RGB->YUV conversion.
+    pub sharp_yuv: bool,
+    /// Near-lossless preprocessing, 0 (strongest) to 100 (none). Only used in `EncodingMode::NearLossless`.
+    pub near_lossless: u8,
+}
+
+impl Default for WebpEncodeSettings {
+    // Mirrors libwebp's WebPConfigInit defaults
+    fn default() -> Self {
+        Self {
+            method: 4,
+            sns_strength: 50,
+            filter_strength: 60,
+            filter_sharpness: 0,
+            segments: 4,
+            pass: 1,
+            preprocessing: 0,
+            sharp_yuv: false,
+            near_lossless: 60,
+        }
+    }
+}
+
+/// libwebp backend, including the auto and target search modes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebpEncoder {
+    pub mode: EncodingMode,
+    /// Lossy quality, or compression effort in the lossless modes, 0-100.
+    pub quality: f32,
+    /// Output budget in bytes for `EncodingMode::TargetSize`.
+    pub target_size: u64,
+    /// Metric and minimum value for `EncodingMode::TargetQuality`.
+    pub quality_metric: QualityMetric,
+    pub metric_target: f64,
+    pub settings: WebpEncodeSettings,
+    /// Bytes of metadata added after encoding, counted against `target_size`.
+    metadata_size: u64,
+}
+
+impl Default for WebpEncoder {
+    fn default() -> Self {
+        Self {
+            mode: EncodingMode::Lossy,
+            quality: 80.0,
+            target_size: 150 * 1024,
+            quality_metric: QualityMetric::Ssim,
+            metric_target: QualityMetric::Ssim.default_target(),
+            settings: WebpEncodeSettings::default(),
+            metadata_size: 0,
+        }
+    }
+}
+
+impl WebpEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encode with `quality` (1-100) instead of the default 80.
+    pub fn quality(mut self, quality: f32) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    /// In the lossless modes quality controls compression effort rather than fidelity.
+    pub fn mode(mut self, mode: EncodingMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Search for the highest quality whose output fits in `target_size` bytes.
+    pub fn target_size(mut self, target_size: u64) -> Self {
+        self.mode = EncodingMode::TargetSize;
+        self.target_size = target_size;
+        self
+    }
+
+    /// Search for the lowest quality whose decoded output scores at least `target` on `metric`.
+    pub fn target_quality(mut self, metric: QualityMetric, target: f64) -> Self {
+        self.mode = EncodingMode::TargetQuality;
+        self.quality_metric = metric;
+        self.metric_target = target;
+        self
+    }
+
+    pub fn settings(mut self, settings: WebpEncodeSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+fn labels<T: ToString>(values: &[T]) -> OptionKind {
+    OptionKind::Choice(values.iter().map(ToString::to_string).collect())
+}
+
+/// Reads an integer option into one of the `u8` settings fields.
+fn to_u8(value: OptionValue, min: u8, max: u8) -> Option<u8> {
+    value.as_i64().map(|value| value.clamp(min as i64, max as i64) as u8)
+}
+
+impl ImageEncoder for WebpEncoder {
+    fn name(&self) -> &str {
+        "WebP"
+    }
+
+    fn extension(&self) -> &str {
+        "webp"
+    }
+
+    fn options_schema(&self) -> Vec<EncoderOption> {
+        let int = |min, max| OptionKind::Int { min, max };
+        let mut options = vec![EncoderOption::new("mode", "Mode", labels(&EncodingMode::ALL))];
+        if self.mode == EncodingMode::NearLossless {
+            options.push(EncoderOption::new("near_lossless", "Preprocessing", int(0, 100)));
+        }
+        match self.mode {
+            EncodingMode::TargetSize => {
+                options.push(EncoderOption::new("target_size_kb", "Target (KB)", int(1, 100_000)));
+            }
+            EncodingMode::TargetQuality => {
+                options.push(EncoderOption::new("metric", "Metric", labels(&QualityMetric::ALL)));
+                let (label, min, max) = match self.quality_metric {
+                    QualityMetric::Ssim => ("Target SSIM", 0.5, 1.0),
+                    QualityMetric::Psnr => ("Target PSNR (dB)", 20.0, 60.0),
+                };
+                options.push(EncoderOption::new("metric_target", label, OptionKind::Float { min, max }));
+            }
+            EncodingMode::Lossless | EncodingMode::NearLossless => {
+                options.push(EncoderOption::new("quality", "Effort", OptionKind::Float { min: 1.0, max: 100.0 }));
+            }
+            _ => {
+                options.push(EncoderOption::new("quality", "Quality", OptionKind::Float { min: 1.0, max: 100.0 }));
+            }
+        }
+        options.extend([
+            EncoderOption::new("method", "Method", int(0, 6)).advanced(),
+            EncoderOption::new("sns_strength", "SNS Strength", int(0, 100)).advanced(),
+            EncoderOption::new("filter_strength", "Filter Strength", int(0, 100)).advanced(),
+            EncoderOption::new("filter_sharpness", "Filter Sharpness", int(0, 7)).advanced(),
+            EncoderOption::new("segments", "Segments", int(1, 4)).advanced(),
+            EncoderOption::new("pass", "Passes", int(1, 10)).advanced(),
+            EncoderOption::new("preprocessing", "Preprocessing", labels(&["None", "Segment smooth", "Dithering"])).advanced(),
+            EncoderOption::new("sharp_yuv", "Sharp YUV", OptionKind::Bool).advanced(),
+        ]);
+        options
+    }
+
+    fn option(&self, key: &str) -> Option<OptionValue> {
+        let settings = &self.settings;
+        Some(match key {
+            "mode" => OptionValue::Choice(EncodingMode::ALL.iter().position(|&mode| mode == self.mode)?),
+            "quality" => OptionValue::Float(self.quality as f64),
+            "target_size_kb" => OptionValue::Int((self.target_size / 1024) as i64),
+            "metric" => OptionValue::Choice(QualityMetric::ALL.iter().position(|&metric| metric == self.quality_metric)?),
+            "metric_target" => OptionValue::Float(self.metric_target),
+            "near_lossless" => OptionValue::Int(settings.near_lossless as i64),
+            "method" => OptionValue::Int(settings.method as i64),
+            "sns_strength" => OptionValue::Int(settings.sns_strength as i64),
+            "filter_strength" => OptionValue::Int(settings.filter_strength as i64),
+            "filter_sharpness" => OptionValue::Int(settings.filter_sharpness as i64),
+            "segments" => OptionValue::Int(settings.segments as i64),
+            "pass" => OptionValue::Int(settings.pass as i64),
+            "preprocessing" => OptionValue::Choice(settings.preprocessing as usize),
+            "sharp_yuv" => OptionValue::Bool(settings.sharp_yuv),
+            _ => return None,
+        })
+    }
+
+    fn set_option(&mut self, key: &str, value: OptionValue) {
+        let settings = &mut self.settings;
+        match key {
+            "mode" => {
+                if let Some(&mode) = value.as_choice().and_then(|index| EncodingMode::ALL.get(index)) {
+                    self.mode = mode;
+                }
+            }
+            "quality" => {
+                if let Some(quality) = value.as_f64() {
+                    self.quality = quality.clamp(0.0, 100.0) as f32;
+                }
+            }
+            "target_size_kb" => {
+                if let Some(kb) = value.as_i64() {
+                    self.target_size = kb.max(1) as u64 * 1024;
+                }
+            }
+            "metric" => {
+                if let Some(&metric) = value.as_choice().and_then(|index| QualityMetric::ALL.get(index)) {
+                    // Targets aren't comparable across metrics
+                    if metric != self.quality_metric {
+                        self.quality_metric = metric;
+                        self.metric_target = metric.default_target();
+                    }
+                }
+            }
+            "metric_target" => {
+                if let Some(target) = value.as_f64() {
+                    self.metric_target = target;
+                }
+            }
+            "near_lossless" => settings.near_lossless = to_u8(value, 0, 100).unwrap_or(settings.near_lossless),
+            "method" => settings.method = to_u8(value, 0, 6).unwrap_or(settings.method),
+            "sns_strength" => settings.sns_strength = to_u8(value, 0, 100).unwrap_or(settings.sns_strength),
+            "filter_strength" => settings.filter_strength = to_u8(value, 0, 100).unwrap_or(settings.filter_strength),
+            "filter_sharpness" => settings.filter_sharpness = to_u8(value, 0, 7).unwrap_or(settings.filter_sharpness),
+            "segments" => settings.segments = to_u8(value, 1, 4).unwrap_or(settings.segments),
+            "pass" => settings.pass = to_u8(value, 1, 10).unwrap_or(settings.pass),
+            "preprocessing" => {
+                if let Some(level) = value.as_choice().filter(|&level| level <= 2) {
+                    settings.preprocessing = level as u8;
+                }
+            }
+            "sharp_yuv" => settings.sharp_yuv = value.as_bool().unwrap_or(settings.sharp_yuv),
+            _ => {}
+        }
+    }
+
+    fn reset_options(&mut self) {
+        *self = Self::default();
+    }
+
+    fn encode(&self, img: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+        self.encode_with_report(img).map(|(webp_data, _)| webp_data)
+    }
+
+    fn encode_with_report(&self, img: &DynamicImage) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+        let settings = &self.settings;
+        match self.mode {
+            EncodingMode::Auto => encode_auto(img, self.quality, settings),
+            EncodingMode::TargetSize => {
+                encode_to_target_size(|quality| encode_to_webp(img, quality, EncodingMode::Lossy, settings), self.target_size, self.metadata_size)
+            }
+            EncodingMode::TargetQuality => encode_to_target_quality(
+                |quality| encode_to_webp(img, quality, EncodingMode::Lossy, settings),
+                |webp_data| Ok(self.quality_metric.measure(img, &decode_webp(webp_data)?)),
+                self.quality_metric,
+                self.metric_target,
+            ),
+            mode => {
+                let webp_data = encode_to_webp(img, self.quality, mode, settings)?;
+                Ok((webp_data, report(mode, self.quality)))
+            }
+        }
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<DynamicImage, ImageError> {
+        decode_webp(data)
+    }
+
+    fn decode_animation(&self, data: &[u8]) -> Result<Animation, ImageError> {
+        Animation::decode_webp(data)
+    }
+
+    fn embed_metadata(&self, data: &[u8], metadata: &Metadata) -> Result<Vec<u8>, ImageError> {
+        mux_metadata(data, metadata)
+    }
+
+    fn leaving_room_for(&self, metadata: &Metadata) -> Box<dyn ImageEncoder> {
+        Box::new(Self { metadata_size: muxed_size(metadata), ..self.clone() })
+    }
+
+    /// `Auto` keeps whichever of lossy and lossless is smaller; the target modes search one quality for every frame,
+    /// scoring target quality on the worst frame.
+    fn encode_animation(&self, animation: &Animation) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+        let settings = &self.settings;
+        match self.mode {
+            EncodingMode::Auto => {
+                let lossy = encode_animation(animation, self.quality, EncodingMode::Lossy, settings)?;
+                let lossless = encode_animation(animation, self.quality, EncodingMode::Lossless, settings)?;
+                let (mode, reason) = if lossless.len() <= lossy.len() {
+                    (EncodingMode::Lossless, format!("lossless is smaller ({} vs {} bytes)", lossless.len(), lossy.len()))
+                } else {
+                    (EncodingMode::Lossy, format!("lossy is smaller ({} vs {} bytes)", lossy.len(), lossless.len()))
+                };
+                let webp_data = if mode == EncodingMode::Lossless { lossless } else { lossy };
+                let mut report = report(mode, self.quality);
+                report.reason = Some(reason);
+                Ok((webp_data, report))
+            }
+            EncodingMode::TargetSize => encode_to_target_size(
+                |quality| encode_animation(animation, quality, EncodingMode::Lossy, settings),
+                self.target_size,
+                self.metadata_size,
+            ),
+            EncodingMode::TargetQuality => {
+                let sources: Vec<DynamicImage> = animation.distinct_frames().into_iter().map(|image| DynamicImage::ImageRgba8(image.clone())).collect();
+                encode_to_target_quality(
+                    |quality| encode_animation(animation, quality, EncodingMode::Lossy, settings),
+                    |webp_data| worst_frame_score(self.quality_metric, &sources, webp_data),
+                    self.quality_metric,
+                    self.metric_target,
+                )
+            }
+            mode => {
+                let webp_data = encode_animation(animation, self.quality, mode, settings)?;
+                Ok((webp_data, report(mode, self.quality)))
+            }
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn ImageEncoder> {
+        Box::new(self.clone())
+    }
+}
+
+fn report(mode: EncodingMode, quality: f32) -> EncodeReport {
+    let quality = match mode {
+        EncodingMode::Lossless | EncodingMode::NearLossless => None,
+        _ => Some(quality),
+    };
+    EncodeReport { mode: Some(mode), quality, ..Default::default() }
+}
+
+fn encode_auto(img: &DynamicImage, quality: f32, settings: &WebpEncodeSettings) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+    let lossy = encode_to_webp(img, quality, EncodingMode::Lossy, settings)?;
+    let lossless = encode_to_webp(img, quality, EncodingMode::Lossless, settings)?;
+    let colors = count_colors(img, AUTO_MAX_PALETTE + 1);
+
+    // Lossy artifacts are most visible on flat graphics, so they keep lossless unless it costs much more
+    let (mode, reason) = if lossless.len() <= lossy.len() {
+        (EncodingMode::Lossless, format!("lossless is smaller ({} vs {} bytes)", lossless.len(), lossy.len()))
+    } else if colors <= AUTO_MAX_PALETTE && (lossless.len() as f32) <= lossy.len() as f32 * AUTO_LOSSLESS_OVERHEAD {
+        (EncodingMode::Lossless, format!("flat graphic ({} colors), lossless within {:.0}% of lossy", colors, (AUTO_LOSSLESS_OVERHEAD - 1.0) * 100.0))
+    } else {
+        (EncodingMode::Lossy, format!("lossy is smaller ({} vs {} bytes)", lossy.len(), lossless.len()))
+    };
+
+    let webp_data = if mode == EncodingMode::Lossless { lossless } else { lossy };
+    let mut report = report(mode, quality);
+    report.reason = Some(reason);
+    Ok((webp_data, report))
+}
+
+/// Binary-searches the highest quality `encode` fits in `target_size` bytes, together with `metadata_size` bytes added afterwards.
+fn encode_to_target_size(
+    encode: impl Fn(f32) -> Result<Vec<u8>, ImageError>,
+    target_size: u64,
+    metadata_size: u64,
+) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+    let mut best = None;
+    let (mut low, mut high) = (0u32, 100u32);
+    // Nothing fits when the metadata alone takes the whole budget
+    while low <= high && metadata_size < target_size {
+        let quality = (low + high) / 2;
+        let webp_data = encode(quality as f32)?;
+        if webp_data.len() as u64 + metadata_size <= target_size {
+            best = Some((webp_data, quality));
+            low = quality + 1;
+        } else if quality == 0 {
+            break;
+        } else {
+            high = quality - 1;
+        }
+    }
+
+    match best {
+        Some((webp_data, quality)) => Ok((webp_data, report(EncodingMode::TargetSize, quality as f32))),
+        None => {
+            // Even the lowest quality is over budget; keep it but flag the image
+            let webp_data = encode(0.0)?;
+            let message = if metadata_size >= target_size {
+                format!("{} bytes of metadata alone exceed target of {} bytes", metadata_size, target_size)
+            } else {
+                format!("{} bytes at minimum quality exceeds target of {} bytes", webp_data.len() as u64 + metadata_size, target_size)
+            };
+            let mut report = report(EncodingMode::TargetSize, 0.0);
+            report.warning = Some(EncodeWarning { status: "Over target size", message });
+            Ok((webp_data, report))
+        }
+    }
+}
+
+/// Binary-searches the lowest quality whose `encode` output `score`s at least `target` on `metric`.
+fn encode_to_target_quality(
+    encode: impl Fn(f32) -> Result<Vec<u8>, ImageError>,
+    score: impl Fn(&[u8]) -> Result<f64, ImageError>,
+    metric: QualityMetric,
+    target: f64,
+) -> Result<(Vec<u8>, EncodeReport), ImageError> {
+    let mut best = None;
+    let (mut low, mut high) = (0u32, 100u32);
+    while low <= high {
+        let quality = (low + high) / 2;
+        let webp_data = encode(quality as f32)?;
+        let value = score(&webp_data)?;
+        if value >= target {
+            best = Some((webp_data, quality, value));
+            if quality == 0 {
+                break;
+            }
+            high = quality - 1;
+        } else {
+            low = quality + 1;
+        }
+    }
+
+    let (webp_data, quality, value, warning) = match best {
+        Some((webp_data, quality, value)) => (webp_data, quality, value, None),
+        None => {
+            // Even the highest quality falls short; keep it but flag the image
+            let webp_data = encode(100.0)?;
+            let value = score(&webp_data)?;
+            let message = format!("{} {} at maximum quality is below target {}", metric, metric.format_value(value), metric.format_value(target));
+            (webp_data, 100, value, Some(EncodeWarning { status: "Below target quality", message }))
+        }
+    };
+
+    let mut report = report(EncodingMode::TargetQuality, quality as f32);
+    report.metric = Some((metric, value));
+    report.warning = warning;
+    Ok((webp_data, report))
+}
+
+/// Lowest `metric` score of the frames decoded from `webp_data` against `sources`, the animation's distinct frames.
+fn worst_frame_score(metric: QualityMetric, sources: &[DynamicImage], webp_data: &[u8]) -> Result<f64, ImageError> {
+    let decoded = Animation::decode_webp(webp_data)?;
+    if decoded.frames.len() != sources.len() {
+        let message = format!("Decoded {} frames, expected {}", decoded.frames.len(), sources.len());
+        return Err(encoding_error(image::ImageFormat::WebP, message));
+    }
+    Ok(decoded.frames.into_iter().zip(sources)
+        .map(|(frame, source)| metric.measure(source, &DynamicImage::ImageRgba8(frame.image)))
+        .fold(f64::INFINITY, f64::min))
+}
+
+pub(crate) fn decode_webp(webp_data: &[u8]) -> Result<DynamicImage, ImageError> {
+    ::webp::Decoder::new(webp_data)
+        .decode()
+        .map(|webp| webp.to_image())
+        .ok_or_else(|| ImageError::Decoding(image::error::DecodingError::new(
+            image::error::ImageFormatHint::Exact(image::ImageFormat::WebP),
+            "Failed to decode encoded WebP",
+        )))
+}
+
+const VP8X_ICC_FLAG: u8 = 0x20;
+const VP8X_ALPHA_FLAG: u8 = 0x10;
+const VP8X_EXIF_FLAG: u8 = 0x08;
+const VP8X_XMP_FLAG: u8 = 0x04;
+const VP8X_ANIMATION_FLAG: u8 = 0x02;
+
+/// Most bytes `mux_metadata` adds for `metadata`: a VP8X header and one padded chunk per payload.
+fn muxed_size(metadata: &Metadata) -> u64 {
+    let payloads: Vec<&Vec<u8>> = [&metadata.icc, &metadata.exif, &metadata.xmp].into_iter().flatten().collect();
+    if payloads.is_empty() {
+        return 0;
+    }
+    18 + payloads.iter().map(|payload| 8 + payload.len() as u64 + (payload.len() as u64 & 1)).sum::<u64>()
+}
+
+/// Rewraps `webp_data` as an extended (VP8X) file carrying `metadata` in ICCP, EXIF and XMP chunks.
+fn mux_metadata(webp_data: &[u8], metadata: &Metadata) -> Result<Vec<u8>, ImageError> {
+    let invalid = |message: &str| encoding_error(image::ImageFormat::WebP, message);
+    if !webp_data.starts_with(b"RIFF") || webp_data.get(8..12) != Some(b"WEBP") {
+        return Err(invalid("Not a WebP file"));
+    }
+
+    let mut flags = 0;
+    let mut canvas = None;
+    let mut image_chunks = Vec::new();
+    for (kind, payload) in riff_chunks(webp_data) {
+        match kind {
+            b"VP8X" if payload.len() >= 10 => {
+                flags |= payload[0] & (VP8X_ALPHA_FLAG | VP8X_ANIMATION_FLAG);
+                let read_u24 = |at: usize| u32::from_le_bytes([payload[at], payload[at + 1], payload[at + 2], 0]) + 1;
+                canvas = Some((read_u24(4), read_u24(7)));
+            }
+            // Replaced by the payloads of `metadata`
+            b"ICCP" | b"EXIF" | b"XMP " => {}
+            _ => {
+                if kind == b"ALPH" {
+                    flags |= VP8X_ALPHA_FLAG;
+                }
+                if kind == b"VP8 " && payload.len() >= 10 && canvas.is_none() {
+                    let read_u14 = |at: usize| (u16::from_le_bytes([payload[at], payload[at + 1]]) & 0x3FFF) as u32;
+                    canvas = Some((read_u14(6), read_u14(8)));
+                }
+                if kind == b"VP8L" && payload.len() >= 5 && canvas.is_none() {
+                    let header = u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
+                    canvas = Some(((header & 0x3FFF) + 1, ((header >> 14) & 0x3FFF) + 1));
+                    if header & (1 << 28) != 0 {
+                        flags |= VP8X_ALPHA_FLAG;
+                    }
+                }
+                image_chunks.push((kind, payload));
+            }
+        }
+    }
+    let (width, height) = canvas.ok_or_else(|| invalid("No VP8/VP8L bitstream to size the canvas"))?;
+
+    if metadata.icc.is_some() {
+        flags |= VP8X_ICC_FLAG;
+    }
+    if metadata.exif.is_some() {
+        flags |= VP8X_EXIF_FLAG;
+    }
+    if metadata.xmp.is_some() {
+        flags |= VP8X_XMP_FLAG;
+    }
+    let mut vp8x = vec![flags, 0, 0, 0];
+    vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+
+    // Chunk order is fixed by the container spec: VP8X, ICCP, image data, EXIF, XMP
+    let mut body = b"WEBP".to_vec();
+    write_chunk(&mut body, b"VP8X", &vp8x);
+    if let Some(icc) = &metadata.icc {
+        write_chunk(&mut body, b"ICCP", icc);
+    }
+    for (kind, payload) in image_chunks {
+        write_chunk(&mut body, kind, payload);
+    }
+    if let Some(exif) = &metadata.exif {
+        write_chunk(&mut body, b"EXIF", exif);
+    }
+    if let Some(xmp) = &metadata.xmp {
+        write_chunk(&mut body, b"XMP ", xmp);
+    }
+    Ok(riff(body))
+}
+
+/// Builds an animated file from ANIM and ANMF chunks.
+///
+/// Each frame stores only the rectangle that changed since the previous one, replacing those pixels without
+/// blending; unchanged frames extend the previous frame's duration instead.
+fn encode_animation(animation: &Animation, quality: f32, mode: EncodingMode, settings: &WebpEncodeSettings) -> Result<Vec<u8>, ImageError> {
+    let (width, height) = animation.dimensions();
+    if animation.frames.is_empty() || width == 0 || height == 0 {
+        return Err(encoding_error(image::ImageFormat::WebP, "Animation has no frames"));
+    }
+
+    // (x, y, duration, changed pixels)
+    let mut frames: Vec<(u32, u32, u32, RgbaImage)> = Vec::new();
+    let mut previous: Option<&RgbaImage> = None;
+    for frame in &animation.frames {
+        let changed = match previous {
+            Some(previous) => changed_rect(previous, &frame.image),
+            None => Some((0, 0, width, height)),
+        };
+        previous = Some(&frame.image);
+        let Some((x, y, w, h)) = changed else {
+            if let Some(last) = frames.last_mut() {
+                last.2 = last.2.saturating_add(frame.delay_ms);
+            }
+            continue;
+        };
+        // Frame offsets are stored halved, so they must be even
+        let (even_x, even_y) = (x & !1, y & !1);
+        let (w, h) = (w + x - even_x, h + y - even_y);
+        frames.push((even_x, even_y, frame.delay_ms, frame.image.view(even_x, even_y, w, h).to_image()));
+    }
+
+    let mut vp8x = vec![VP8X_ANIMATION_FLAG | if animation.has_alpha() { VP8X_ALPHA_FLAG } else { 0 }, 0, 0, 0];
+    vp8x.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+    // Background color (BGRA, white) is only a hint; players start from a transparent canvas
+    let mut anim = vec![0xFF; 4];
+    anim.extend_from_slice(&animation.loop_count.to_le_bytes());
+
+    let mut body = b"WEBP".to_vec();
+    write_chunk(&mut body, b"VP8X", &vp8x);
+    write_chunk(&mut body, b"ANIM", &anim);
+    for (x, y, duration, image) in frames {
+        let mut anmf = Vec::new();
+        for value in [x / 2, y / 2, image.width() - 1, image.height() - 1, duration.min(0xFF_FFFF)] {
+            anmf.extend_from_slice(&value.to_le_bytes()[..3]);
+        }
+        // Do not blend, do not dispose
+        anmf.push(0b10);
+        let still = encode_to_webp(&DynamicImage::ImageRgba8(image), quality, mode, settings)?;
+        for (kind, payload) in riff_chunks(&still).filter(|(kind, _)| matches!(*kind, b"ALPH" | b"VP8 " | b"VP8L")) {
+            write_chunk(&mut anmf, kind, payload);
+        }
+        write_chunk(&mut body, b"ANMF", &anmf);
+    }
+    Ok(riff(body))
+}
+
+/// Bounding box `(x, y, width, height)` of the pixels that differ between two same-sized frames.
+fn changed_rect(previous: &RgbaImage, current: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in current.enumerate_pixels() {
+        if previous.get_pixel(x, y) != pixel {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    (min_x != u32::MAX).then(|| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+/// Appends a RIFF chunk, padded to an even length.
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8], payload: &[u8]) {
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Wraps chunks starting with the `WEBP` form type in a RIFF header.
+fn riff(body: Vec<u8>) -> Vec<u8> {
+    let mut output = b"RIFF".to_vec();
+    output.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    output.extend_from_slice(&body);
+    output
+}
+
+/// Images with at most this many distinct colors are treated as flat graphics in auto mode.
+const AUTO_MAX_PALETTE: usize = 256;
+/// How much larger than lossy a flat graphic's lossless encoding may be and still be preferred.
+const AUTO_LOSSLESS_OVERHEAD: f32 = 1.25;
+
+/// Counts distinct RGBA colors, stopping once `limit` is reached.
+fn count_colors(img: &DynamicImage, limit: usize) -> usize {
+    let mut colors = std::collections::HashSet::new();
+    for pixel in img.to_rgba8().pixels() {
+        colors.insert(pixel.0);
+        if colors.len() >= limit {
+            break;
+        }
+    }
+    colors.len()
+}
+
+fn encode_to_webp(img: &DynamicImage, quality: f32, mode: EncodingMode, settings: &WebpEncodeSettings) -> Result<Vec<u8>, ImageError> {
+    let encoder = ::webp::Encoder::from_image(img).map_err(|e| encoding_error(image::ImageFormat::WebP, e.to_string()))?;
+    let config = webp_config(quality, mode, settings).map_err(|_| encoding_error(image::ImageFormat::WebP, "Failed to initialize WebP config"))?;
+    let webp = encoder.encode_advanced(&config).map_err(|e| encoding_error(image::ImageFormat::WebP, format!("{:?}", e)))?;
+    Ok(webp.to_vec())
+}
+
+fn webp_config(quality: f32, mode: EncodingMode, settings: &WebpEncodeSettings) -> Result<::webp::WebPConfig, ()> {
+    let mut config = ::webp::WebPConfig::new()?;
+    config.quality = quality.clamp(0.0, 100.0);
+    config.method = settings.method.min(6) as i32;
+    config.sns_strength = settings.sns_strength.min(100) as i32;
+    config.filter_strength = settings.filter_strength.min(100) as i32;
+    config.filter_sharpness = settings.filter_sharpness.min(7) as i32;
+    config.segments = settings.segments.clamp(1, 4) as i32;
+    config.pass = settings.pass.clamp(1, 10) as i32;
+    config.preprocessing = settings.preprocessing.min(2) as i32;
+    config.use_sharp_yuv = settings.sharp_yuv as i32;
+    match mode {
+        // The search modes drive lossy/lossless encodes from `encode_with_report`
+        EncodingMode::Lossy | EncodingMode::Auto | EncodingMode::TargetSize | EncodingMode::TargetQuality => {}
+        EncodingMode::Lossless => {
+            config.lossless = 1;
+            config.alpha_compression = 0;
+        }
+        EncodingMode::NearLossless => {
+            config.lossless = 1;
+            config.alpha_compression = 0;
+            config.near_lossless = settings.near_lossless.min(100) as i32;
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `(FourCC, payload)` of every top-level chunk, checking the RIFF size on the way.
+    fn chunks(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize, data.len() - 8, "RIFF size");
+        assert_eq!(&data[8..12], b"WEBP");
+        riff_chunks(data).map(|(kind, payload)| (String::from_utf8_lossy(kind).into_owned(), payload.to_vec())).collect()
+    }
+
+    fn kinds(chunks: &[(String, Vec<u8>)]) -> Vec<&str> {
+        chunks.iter().map(|(kind, _)| kind.as_str()).collect()
+    }
+
+    /// Four flat color blocks, the kind of graphic lossy artifacts spoil.
+    fn flat_graphic() -> DynamicImage {
+        let colors = [[230, 40, 40], [40, 160, 60], [30, 60, 200], [250, 250, 250]];
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(64, 64, |x, y| image::Rgb(colors[(x / 32 + 2 * (y / 32)) as usize])))
+    }
+
+    /// A gradient under pseudo-random noise, with far too many colors for a palette.
+    fn noisy_photo() -> DynamicImage {
+        let mut state = 0x2545_f491u32;
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(96, 64, |x, y| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = (state >> 24) as u8 / 4;
+            image::Rgb([(x * 2) as u8 + noise, (y * 3) as u8 + noise, 128u8.wrapping_add(noise)])
+        }))
+    }
+
+    #[test]
+    fn auto_mode_keeps_flat_graphics_lossless() {
+        let settings = WebpEncodeSettings::default();
+        assert_eq!(count_colors(&flat_graphic(), AUTO_MAX_PALETTE + 1), 4);
+        let (webp_data, report) = encode_auto(&flat_graphic(), 80.0, &settings).unwrap();
+        assert_eq!(report.mode, Some(EncodingMode::Lossless));
+        assert_eq!(report.quality, None);
+        assert!(report.reason.is_some());
+        assert!(decode_webp(&webp_data).unwrap().to_rgb8() == flat_graphic().to_rgb8());
+
+        assert_eq!(count_colors(&noisy_photo(), AUTO_MAX_PALETTE + 1), AUTO_MAX_PALETTE + 1);
+        let (_, report) = encode_auto(&noisy_photo(), 80.0, &settings).unwrap();
+        assert_eq!(report.mode, Some(EncodingMode::Lossy));
+        assert_eq!(report.quality, Some(80.0));
+        assert!(report.reason.unwrap().starts_with("lossy is smaller"));
+    }
+
+    #[test]
+    fn target_size_search_fits_the_budget() {
+        let photo = noisy_photo();
+        let encode = |quality: f32| encode_to_webp(&photo, quality, EncodingMode::Lossy, &WebpEncodeSettings::default());
+        let target = encode(100.0).unwrap().len() as u64 * 2 / 3;
+        let (webp_data, report) = encode_to_target_size(encode, target, 0).unwrap();
+        assert!(webp_data.len() as u64 <= target);
+        assert!(report.warning.is_none());
+        let quality = report.quality.unwrap();
+        assert!(quality > 0.0 && quality < 100.0, "{}", quality);
+
+        // Room for metadata comes out of the same budget
+        let (webp_data, with_room) = encode_to_target_size(encode, target, target / 3).unwrap();
+        assert!(webp_data.len() as u64 + target / 3 <= target);
+        assert!(with_room.quality.unwrap() <= quality);
+        assert!(with_room.warning.is_none());
+    }
+
+    #[test]
+    fn flags_budgets_nothing_fits() {
+        let photo = noisy_photo();
+        let encode = |quality: f32| encode_to_webp(&photo, quality, EncodingMode::Lossy, &WebpEncodeSettings::default());
+        let (_, report) = encode_to_target_size(encode, 64, 0).unwrap();
+        let warning = report.warning.unwrap();
+        assert_eq!(warning.status, "Over target size");
+        assert!(warning.message.contains("at minimum quality"), "{}", warning.message);
+        assert_eq!(report.quality, Some(0.0));
+
+        let (_, report) = encode_to_target_size(encode, 100_000, 100_000).unwrap();
+        assert!(report.warning.unwrap().message.contains("metadata alone"));
+    }
+
+    #[test]
+    fn target_quality_search_meets_the_metric() {
+        let photo = noisy_photo();
+        let (webp_data, report) = WebpEncoder::new().target_quality(QualityMetric::Ssim, 0.9).encode_with_report(&photo).unwrap();
+        assert!(report.warning.is_none());
+        let (metric, value) = report.metric.unwrap();
+        assert_eq!(metric, QualityMetric::Ssim);
+        assert!(value >= 0.9);
+        assert_eq!(QualityMetric::Ssim.measure(&photo, &decode_webp(&webp_data).unwrap()), value);
+        let quality = report.quality.unwrap();
+        assert!(quality > 0.0 && quality < 100.0, "{}", quality);
+
+        // A stricter target needs at least as high a quality
+        let (_, stricter) = WebpEncoder::new().target_quality(QualityMetric::Ssim, 0.97).encode_with_report(&photo).unwrap();
+        assert!(stricter.quality.unwrap() >= quality);
+    }
+
+    #[test]
+    fn flags_quality_targets_out_of_reach() {
+        let (_, report) = WebpEncoder::new().target_quality(QualityMetric::Psnr, 1000.0).encode_with_report(&noisy_photo()).unwrap();
+        assert_eq!(report.warning.unwrap().status, "Below target quality");
+        assert_eq!(report.quality, Some(100.0));
+        assert!(report.metric.unwrap().1 < 1000.0);
+    }
+
+    #[test]
+    fn searches_target_modes_over_every_frame() {
+        // The noisy frame comes last, so a search on the first frame alone would settle too high a quality or too low a size
+        let flat = RgbaImage::from_pixel(96, 64, image::Rgba([90, 140, 200, 255]));
+        let frames = [flat.clone(), flat, noisy_photo().into_rgba8()].map(|image| crate::animation::Frame { image, delay_ms: 100 });
+        let animation = Animation { frames: frames.to_vec(), loop_count: 0 };
+        let full_quality = encode_animation(&animation, 100.0, EncodingMode::Lossy, &WebpEncodeSettings::default()).unwrap();
+
+        let target = full_quality.len() as u64 * 2 / 3;
+        let (webp_data, report) = WebpEncoder::new().target_size(target).encode_animation(&animation).unwrap();
+        assert!(webp_data.len() as u64 <= target, "{} bytes", webp_data.len());
+        assert_eq!(report.mode, Some(EncodingMode::TargetSize));
+        assert!(report.warning.is_none());
+        assert_eq!(Animation::decode_webp(&webp_data).unwrap().frames.len(), 2);
+
+        let (webp_data, report) = WebpEncoder::new().target_quality(QualityMetric::Ssim, 0.9).encode_animation(&animation).unwrap();
+        let (metric, value) = report.metric.unwrap();
+        assert_eq!(metric, QualityMetric::Ssim);
+        assert!(value >= 0.9);
+        let decoded = Animation::decode_webp(&webp_data).unwrap();
+        let noisy = QualityMetric::Ssim.measure(&DynamicImage::ImageRgba8(animation.frames[2].image.clone()), &DynamicImage::ImageRgba8(decoded.frames[1].image.clone()));
+        assert_eq!(noisy, value);
+
+        let (_, report) = WebpEncoder::new().target_size(64).encode_animation(&animation).unwrap();
+        assert_eq!(report.warning.unwrap().status, "Over target size");
+    }
+
+    #[test]
+    fn reserves_the_bytes_metadata_adds() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(33, 17, image::Rgb([200, 100, 50])));
+        let simple = encode_to_webp(&img, 75.0, EncodingMode::Lossy, &WebpEncodeSettings::default()).unwrap();
+        let metadata = sample_metadata();
+        assert_eq!(mux_metadata(&simple, &metadata).unwrap().len() - simple.len(), muxed_size(&metadata) as usize);
+        assert_eq!(muxed_size(&Metadata::default()), 0);
+    }
+
+    fn sample_metadata() -> Metadata {
+        // Odd lengths, so every chunk needs its padding byte
+        Metadata { exif: Some(b"II*\0\x08\0\0\0\0\0\0".to_vec()), xmp: Some(b"<x:xmpmeta/>x".to_vec()), icc: Some(vec![7; 131]) }
+    }
+
+    #[test]
+    fn muxes_metadata_into_a_simple_file() {
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(33, 17, image::Rgb([200, 100, 50])));
+        let simple = encode_to_webp(&img, 75.0, EncodingMode::Lossy, &WebpEncodeSettings::default()).unwrap();
+        assert_eq!(kinds(&chunks(&simple)), vec!["VP8 "]);
+
+        let muxed = mux_metadata(&simple, &sample_metadata()).unwrap();
+        let muxed_chunks = chunks(&muxed);
+        assert_eq!(kinds(&muxed_chunks), vec!["VP8X", "ICCP", "VP8 ", "EXIF", "XMP "]);
+        let vp8x = &muxed_chunks[0].1;
+        assert_eq!(vp8x[0], VP8X_ICC_FLAG | VP8X_EXIF_FLAG | VP8X_XMP_FLAG);
+        assert_eq!(&vp8x[4..10], &[32, 0, 0, 16, 0, 0], "canvas size minus one");
+        assert_eq!(Metadata::read(&muxed), sample_metadata());
+        assert_eq!(image::load_from_memory(&muxed).unwrap().dimensions(), (33, 17));
+    }
+
+    #[test]
+    fn muxes_metadata_into_an_extended_file_with_alpha() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(20, 10, |x, _| image::Rgba([0, 0, 255, (x * 12) as u8])));
+        let extended = encode_to_webp(&img, 75.0, EncodingMode::Lossy, &WebpEncodeSettings::default()).unwrap();
+        assert_eq!(kinds(&chunks(&extended)), vec!["VP8X", "ALPH", "VP8 "]);
+
+        let muxed = mux_metadata(&extended, &Metadata { xmp: Some(b"<x/>".to_vec()), ..Default::default() }).unwrap();
+        let muxed_chunks = chunks(&muxed);
+        assert_eq!(kinds(&muxed_chunks), vec!["VP8X", "ALPH", "VP8 ", "XMP "]);
+        assert_eq!(muxed_chunks[0].1[0], VP8X_ALPHA_FLAG | VP8X_XMP_FLAG);
+        assert_eq!(&muxed_chunks[0].1[4..10], &[19, 0, 0, 9, 0, 0]);
+
+        // Muxing again replaces the metadata instead of adding to it
+        let remuxed = mux_metadata(&muxed, &sample_metadata()).unwrap();
+        assert_eq!(kinds(&chunks(&remuxed)), vec!["VP8X", "ICCP", "ALPH", "VP8 ", "EXIF", "XMP "]);
+        assert_eq!(Metadata::read(&remuxed), sample_metadata());
+    }
+
+    #[test]
+    fn muxes_metadata_into_a_lossless_file() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(5, 3, image::Rgba([1, 2, 3, 128])));
+        let lossless = encode_to_webp(&img, 100.0, EncodingMode::Lossless, &WebpEncodeSettings::default()).unwrap();
+        let muxed = mux_metadata(&lossless, &Metadata { icc: Some(vec![1; 4]), ..Default::default() }).unwrap();
+        let muxed_chunks = chunks(&muxed);
+        assert_eq!(kinds(&muxed_chunks), vec!["VP8X", "ICCP", "VP8L"]);
+        // The alpha flag comes from the VP8L header
+        assert_eq!(muxed_chunks[0].1[0], VP8X_ICC_FLAG | VP8X_ALPHA_FLAG);
+        assert_eq!(&muxed_chunks[0].1[4..10], &[4, 0, 0, 2, 0, 0]);
+    }
+
+    #[test]
+    fn rejects_non_webp_data() {
+        assert!(mux_metadata(b"\x89PNG\r\n\x1a\n", &sample_metadata()).is_err());
+        assert!(mux_metadata(b"RIFF\x04\0\0\0WEBP", &sample_metadata()).is_err());
+    }
+
+    /// 16x12 red canvas; then a blue 4x4 square at (5, 3); the same again; then a green pixel in the corner.
+    fn sample_animation() -> Animation {
+        let first = RgbaImage::from_pixel(16, 12, image::Rgba([255, 0, 0, 255]));
+        let mut second = first.clone();
+        for (x, y) in (5..9).flat_map(|x| (3..7).map(move |y| (x, y))) {
+            second.put_pixel(x, y, image::Rgba([0, 0, 255, 255]));
+        }
+        let mut fourth = second.clone();
+        fourth.put_pixel(15, 11, image::Rgba([0, 255, 0, 255]));
+        let frames = [(first, 100), (second.clone(), 200), (second, 50), (fourth, 300)]
+            .into_iter()
+            .map(|(image, delay_ms)| crate::animation::Frame { image, delay_ms })
+            .collect();
+        Animation { frames, loop_count: 3 }
+    }
+
+    /// `(x, y, width, height, duration, flags, bitstream FourCCs)` of an ANMF payload.
+    fn anmf(payload: &[u8]) -> (u32, u32, u32, u32, u32, u8, Vec<String>) {
+        let u24 = |at: usize| u32::from_le_bytes([payload[at], payload[at + 1], payload[at + 2], 0]);
+        let mut position = 16;
+        let mut kinds = Vec::new();
+        while position + 8 <= payload.len() {
+            let length = u32::from_le_bytes(payload[position + 4..position + 8].try_into().unwrap()) as usize;
+            kinds.push(String::from_utf8_lossy(&payload[position..position + 4]).into_owned());
+            position += 8 + length + (length & 1);
+        }
+        (u24(0) * 2, u24(3) * 2, u24(6) + 1, u24(9) + 1, u24(12), payload[15], kinds)
+    }
+
+    #[test]
+    fn encodes_changed_rectangles_as_anmf_frames() {
+        let animation = sample_animation();
+        let data = encode_animation(&animation, 75.0, EncodingMode::Lossless, &WebpEncodeSettings::default()).unwrap();
+        let file_chunks = chunks(&data);
+        assert_eq!(kinds(&file_chunks), vec!["VP8X", "ANIM", "ANMF", "ANMF", "ANMF"]);
+        assert_eq!(file_chunks[0].1[0], VP8X_ANIMATION_FLAG);
+        assert_eq!(&file_chunks[0].1[4..10], &[15, 0, 0, 11, 0, 0]);
+        assert_eq!(&file_chunks[1].1[4..6], &3u16.to_le_bytes());
+
+        let frames: Vec<_> = file_chunks[2..].iter().map(|(_, payload)| anmf(payload)).collect();
+        let vp8l = vec!["VP8L".to_string()];
+        // Whole canvas first; every frame replaces its rectangle (no blending) and is left in place (no disposal)
+        assert_eq!(frames[0], (0, 0, 16, 12, 100, 0b10, vp8l.clone()));
+        // The square at (5, 3) grows to start on even offsets; the unchanged third frame extends its duration
+        assert_eq!(frames[1], (4, 2, 5, 5, 250, 0b10, vp8l.clone()));
+        assert_eq!(frames[2], (14, 10, 2, 2, 300, 0b10, vp8l));
+
+        // Lossless frames composite back to the source canvases
+        let decoded = Animation::decode_webp(&data).unwrap();
+        let expected = [&animation.frames[0], &animation.frames[1], &animation.frames[3]];
+        assert_eq!(decoded.frames.len(), 3);
+        for (frame, source) in decoded.frames.iter().zip(expected) {
+            assert!(frame.image == source.image);
+        }
+        assert_eq!(decoded.frames.iter().map(|frame| frame.delay_ms).collect::<Vec<_>>(), vec![100, 250, 300]);
+        assert_eq!(decoded.loop_count, 3);
+    }
+
+    #[test]
+    fn lossy_animation_frames_carry_alpha() {
+        let mut animation = sample_animation();
+        animation.frames[0].image.put_pixel(0, 0, image::Rgba([255, 0, 0, 0]));
+        let data = encode_animation(&animation, 75.0, EncodingMode::Lossy, &WebpEncodeSettings::default()).unwrap();
+        let file_chunks = chunks(&data);
+        assert_eq!(file_chunks[0].1[0], VP8X_ANIMATION_FLAG | VP8X_ALPHA_FLAG);
+        assert_eq!(anmf(&file_chunks[2].1).6, vec!["ALPH".to_string(), "VP8 ".to_string()]);
+        assert_eq!(Animation::decode_webp(&data).unwrap().frames.len(), 3);
+    }
+
+    #[test]
+    fn rejects_empty_animations() {
+        let empty = Animation { frames: Vec::new(), loop_count: 0 };
+        assert!(encode_animation(&empty, 75.0, EncodingMode::Lossy, &WebpEncodeSettings::default()).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+// folder_scan.rs
+use crate::image_processing::is_supported_input;
+use glob::{MatchOptions, Pattern};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// What a folder scan does with symbolic links.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    Skip,
+    /// Follow links to files and directories; directories already visited are not entered again.
+    Follow,
+}
+
+impl SymlinkPolicy {
+    pub const ALL: [SymlinkPolicy; 2] = [SymlinkPolicy::Skip, SymlinkPolicy::Follow];
+}
+
+impl std::fmt::Display for SymlinkPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymlinkPolicy::Skip => write!(f, "Skip"),
+            SymlinkPolicy::Follow => write!(f, "Follow"),
+        }
+    }
+}
+
+/// Recursive search for images under a folder.
+///
+/// Patterns are globs matched against the path relative to the folder, using `/` separators; a pattern
+/// without a `/` matches the file or folder name at any depth, so `*.png` and `drafts` work as expected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FolderScan {
+    /// Files must match one of these; empty accepts every decodable image.
+    pub include: Vec<String>,
+    /// Files and folders matching any of these are skipped.
+    pub exclude: Vec<String>,
+    /// Descend into and pick up names starting with a dot.
+    pub include_hidden: bool,
+    pub symlinks: SymlinkPolicy,
+    /// File size bounds in bytes, inclusive.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+}
+
+impl Default for FolderScan {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_hidden: false,
+            symlinks: SymlinkPolicy::Skip,
+            min_size: None,
+            max_size: None,
+        }
+    }
+}
+
+impl FolderScan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn exclude<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn include_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden = include_hidden;
+        self
+    }
+
+    pub fn symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Only pick files of at least `min_size` and at most `max_size` bytes.
+    pub fn size_range(mut self, min_size: Option<u64>, max_size: Option<u64>) -> Self {
+        self.min_size = min_size;
+        self.max_size = max_size;
+        self
+    }
+
+    /// Every matching image under `root`, sorted by path. Unreadable subfolders are skipped.
+    pub fn run(&self, root: &Path) -> Result<Vec<PathBuf>, String> {
+        let include = compile(&self.include)?;
+        let exclude = compile(&self.exclude)?;
+        let entries = std::fs::read_dir(root).map_err(|e| format!("{}: {}", root.display(), e))?;
+
+        let mut files = Vec::new();
+        let mut visited = HashSet::new();
+        visited.insert(root.canonicalize().unwrap_or_else(|_| root.to_path_buf()));
+        let mut pending: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        while let Some(path) = pending.pop() {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if (hidden && !self.include_hidden) || exclude.iter().any(|pattern| matches(pattern, relative)) {
+                continue;
+            }
+            let Ok(link_metadata) = std::fs::symlink_metadata(&path) else { continue };
+            if link_metadata.file_type().is_symlink() && self.symlinks == SymlinkPolicy::Skip {
+                continue;
+            }
+            // Follows the link, if any
+            let Ok(metadata) = std::fs::metadata(&path) else { continue };
+
+            if metadata.is_dir() {
+                // Links can point back up the tree
+                if visited.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+                    if let Ok(entries) = std::fs::read_dir(&path) {
+                        pending.extend(entries.filter_map(|entry| entry.ok().map(|entry| entry.path())));
+                    }
+                }
+            } else if metadata.is_file()
+                && self.min_size.is_none_or(|min| metadata.len() >= min)
+                && self.max_size.is_none_or(|max| metadata.len() <= max)
+                && (include.is_empty() || include.iter().any(|pattern| matches(pattern, relative)))
+                && is_supported_input(&path)
+            {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Pattern>, String> {
+    patterns.iter()
+        .map(|pattern| pattern.trim())
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| Pattern::new(pattern).map_err(|e| format!("{}: {}", pattern, e)))
+        .collect()
+}
+
+fn matches(pattern: &Pattern, relative: &Path) -> bool {
+    let options = MatchOptions { case_sensitive: false, require_literal_separator: true, require_literal_leading_dot: false };
+    if pattern.as_str().contains('/') {
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        pattern.matches_with(&relative, options)
+    } else {
+        relative.file_name().is_some_and(|name| pattern.matches_with(&name.to_string_lossy(), options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// root/
+    ///   a.jpg (500 B), b.png (2000 B), notes.txt, .hidden.png, .git/c.png
+    ///   sub/d.png (1000 B), sub/drafts/e.png (3000 B)
+    fn tree() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let file = |path: &str, size: usize| {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0u8; size]).unwrap();
+        };
+        file("a.jpg", 500);
+        file("b.png", 2000);
+        file("notes.txt", 100);
+        file(".hidden.png", 100);
+        file(".git/c.png", 100);
+        file("sub/d.png", 1000);
+        file("sub/drafts/e.png", 3000);
+        root
+    }
+
+    fn relative(root: &Path, files: Vec<PathBuf>) -> Vec<String> {
+        files.iter().map(|path| path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/")).collect()
+    }
+
+    #[test]
+    fn finds_images_recursively() {
+        let root = tree();
+        let files = FolderScan::new().run(root.path()).unwrap();
+        assert_eq!(relative(root.path(), files), vec!["a.jpg", "b.png", "sub/d.png", "sub/drafts/e.png"]);
+    }
+
+    #[test]
+    fn applies_include_and_exclude_globs() {
+        let root = tree();
+        let scan = |scan: FolderScan| relative(root.path(), scan.run(root.path()).unwrap());
+        assert_eq!(scan(FolderScan::new().include(["*.PNG"])), vec!["b.png", "sub/d.png", "sub/drafts/e.png"]);
+        // With a slash, patterns match the whole relative path and `*` stays within one folder
+        assert_eq!(scan(FolderScan::new().include(["sub/*.png"])), vec!["sub/d.png"]);
+        assert_eq!(scan(FolderScan::new().include(["sub/**/*.png"])), vec!["sub/d.png", "sub/drafts/e.png"]);
+        assert_eq!(scan(FolderScan::new().exclude(["drafts", "*.jpg"])), vec!["b.png", "sub/d.png"]);
+        assert_eq!(scan(FolderScan::new().include(["*.png", " "]).exclude(["sub/*"])), vec!["b.png"]);
+    }
+
+    #[test]
+    fn includes_hidden_files_on_request() {
+        let root = tree();
+        let files = FolderScan::new().include_hidden(true).run(root.path()).unwrap();
+        assert_eq!(relative(root.path(), files), vec![".git/c.png", ".hidden.png", "a.jpg", "b.png", "sub/d.png", "sub/drafts/e.png"]);
+    }
+
+    #[test]
+    fn filters_by_size_inclusively() {
+        let root = tree();
+        let files = FolderScan::new().size_range(Some(1000), Some(2000)).run(root.path()).unwrap();
+        assert_eq!(relative(root.path(), files), vec!["b.png", "sub/d.png"]);
+        let files = FolderScan::new().size_range(Some(2001), None).run(root.path()).unwrap();
+        assert_eq!(relative(root.path(), files), vec!["sub/drafts/e.png"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_or_follows_symlinks() {
+        let root = tree();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("f.png"), [0u8; 10]).unwrap();
+        std::os::unix::fs::symlink(root.path().join("sub/d.png"), root.path().join("link.png")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("linked")).unwrap();
+        // Points back up the tree
+        std::os::unix::fs::symlink(root.path(), root.path().join("sub/loop")).unwrap();
+
+        let files = FolderScan::new().run(root.path()).unwrap();
+        assert_eq!(relative(root.path(), files), vec!["a.jpg", "b.png", "sub/d.png", "sub/drafts/e.png"]);
+        let files = FolderScan::new().symlinks(SymlinkPolicy::Follow).run(root.path()).unwrap();
+        assert_eq!(relative(root.path(), files), vec!["a.jpg", "b.png", "link.png", "linked/f.png", "sub/d.png", "sub/drafts/e.png"]);
+    }
+
+    #[test]
+    fn reports_bad_patterns_and_missing_folders() {
+        let root = tree();
+        assert!(FolderScan::new().include(["[*.png"]).run(root.path()).is_err());
+        assert!(FolderScan::new().run(&root.path().join("missing")).is_err());
+    }
+}
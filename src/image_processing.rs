@@ -0,0 +1,1190 @@
+// image_processing.rs
+use crate::animation::{Animation, Frame};
+use crate::color::{ColorManagement, IccProfile};
+use crate::conversion::{ConversionJob, ConversionOptions, ConversionUpdate, FormatOutput, ImageDetail};
+use crate::encoders::{EncodeReport, EncodeWarning, ImageEncoder};
+use crate::metadata::{self, Metadata, Orientation};
+use crate::metrics::FidelityMetrics;
+use crate::pixel_format;
+use crate::resize::{self, CropRect};
+use crate::utils::{Logger, measure_time, get_memory_usage};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use image::error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::io::Reader as ImageReader;
+use image::{ColorType, DynamicImage, GenericImageView, ImageError, ImageFormat, RgbaImage};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use parking_lot::Mutex;
+use std::time::Instant;
+use std::sync::mpsc::Sender;
+
+/// File extensions accepted as conversion input: everything the `image` decoders read, plus SVG.
+pub const INPUT_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "jfif", "png", "webp", "gif", "bmp", "tif", "tiff", "tga", "qoi", "pnm", "pbm", "pgm", "ppm", "pam",
+    "ico", "hdr", "exr", "ff", "dds", "svg",
+];
+
+/// Format of the file at `path` by its magic bytes, or by extension for headerless formats such as TGA.
+pub fn detect_format(path: &Path) -> Option<ImageFormat> {
+    let mut header = Vec::with_capacity(32);
+    File::open(path).ok()?.take(32).read_to_end(&mut header).ok()?;
+    image::guess_format(&header).ok().or_else(|| ImageFormat::from_path(path).ok())
+}
+
+/// Whether `path` holds an image `convert_images` can decode, judged by content first.
+pub fn is_supported_input(path: &Path) -> bool {
+    source_format_name(path).is_some()
+}
+
+/// Display name of the format of the file at `path` when `convert_images` can decode it, e.g. "JPEG" or "SVG".
+pub fn source_format_name(path: &Path) -> Option<&'static str> {
+    match detect_format(path) {
+        Some(format) => format.can_read().then(|| format_name(format)),
+        None => {
+            let mut head = Vec::with_capacity(1024);
+            File::open(path).ok()?.take(1024).read_to_end(&mut head).ok()?;
+            is_svg(&head).then_some("SVG")
+        }
+    }
+}
+
+/// Short display name of a source format, e.g. "JPEG".
+pub fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "PNG",
+        ImageFormat::Jpeg => "JPEG",
+        ImageFormat::Gif => "GIF",
+        ImageFormat::WebP => "WebP",
+        ImageFormat::Pnm => "PNM",
+        ImageFormat::Tiff => "TIFF",
+        ImageFormat::Tga => "TGA",
+        ImageFormat::Dds => "DDS",
+        ImageFormat::Bmp => "BMP",
+        ImageFormat::Ico => "ICO",
+        ImageFormat::Hdr => "HDR",
+        ImageFormat::OpenExr => "OpenEXR",
+        ImageFormat::Farbfeld => "Farbfeld",
+        ImageFormat::Avif => "AVIF",
+        ImageFormat::Qoi => "QOI",
+        _ => "Unknown",
+    }
+}
+
+/// Converts `job`, writing every format it names among `encoders`.
+pub fn convert_images(
+    job: &ConversionJob,
+    encoders: &[Box<dyn ImageEncoder>],
+    log_messages: Arc<Mutex<Vec<String>>>,
+    sender: Option<&Sender<ConversionUpdate>>,
+) -> Vec<ImageDetail> {
+    let logger = Logger::new(log_messages);
+    logger.log("Starting convert_images function".to_string());
+    logger.log(get_memory_usage());
+
+    // Receivers may go away mid-run (e.g. the window was closed); keep converting regardless
+    let notify = |update: ConversionUpdate| {
+        if let Some(sender) = sender {
+            sender.send(update).unwrap_or_default();
+        }
+    };
+
+    let input_files = &job.input_files;
+    let options = &job.options;
+
+    if input_files.is_empty() {
+        logger.log("No input files selected".to_string());
+        notify(ConversionUpdate::Completed);
+        return Vec::new();
+    }
+
+    let total_files = input_files.len();
+    logger.log(format!("Total files to process: {}", total_files));
+
+    let selected: Vec<(&str, Option<&dyn ImageEncoder>)> = options.encoders.iter()
+        .map(|name| (name.as_str(), encoders.iter().find(|encoder| encoder.name().eq_ignore_ascii_case(name)).map(|encoder| encoder.as_ref())))
+        .collect();
+    for (name, encoder) in &selected {
+        match encoder {
+            Some(encoder) => logger.log(format!("Using {} encoder", encoder.name())),
+            None => logger.log(format!("Error: no encoder registered as {}", name)),
+        }
+    }
+
+    let start_time = Instant::now();
+
+    let collisions = output_collisions(job);
+    logger.log("Starting parallel iteration over input files".to_string());
+    let completed = AtomicUsize::new(0);
+    let original_sizes = Mutex::new(Vec::new());
+    let compressed_sizes = Mutex::new(Vec::new());
+    let details = input_files.par_iter().enumerate().map(|(index, input_path)| {
+        logger.log(format!("Processing file: {}", input_path.display()));
+
+        let original_size = std::fs::metadata(input_path).map(|m| m.len()).unwrap_or(0);
+        let mut detail = ImageDetail::new(
+            input_path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            original_size,
+        );
+
+        // Update status to "Processing"
+        notify(ConversionUpdate::StatusUpdate(index, "Processing...".to_string(), None));
+
+        let (img_result, load_duration) = measure_time(|| load_image(input_path, options));
+        logger.log(format!("Loading image {} took {:?}", input_path.display(), load_duration));
+        if let Ok(loaded) = &img_result {
+            logger.log(format!("Image loaded successfully ({})", loaded.format));
+            detail.source_format = Some(loaded.format.to_string());
+            notify(ConversionUpdate::SourceFormat(index, loaded.format.to_string()));
+        }
+
+        let result = match (img_result, collisions[index]) {
+            (Err(e), _) => Err(format!("Failed to load: {}", e)),
+            (Ok(_), _) if selected.is_empty() => Err("No output formats selected".to_string()),
+            (Ok(_), Some(first)) => Err(collision_error(first)),
+            (Ok(Loaded { data, animation: Some(animation), .. }), None) => {
+                let (frame_count, duration) = (animation.frames.len(), animation.duration_ms());
+                logger.log(format!("Animation in {}: {} frames, {} ms, loop count {}", input_path.display(), frame_count, duration, animation.loop_count));
+                detail.frame_count = Some(frame_count);
+                detail.duration_ms = Some(duration);
+                notify(ConversionUpdate::Animated(index, frame_count, duration));
+                let animation = animation.retimed(options.animation_speed, options.loop_count);
+                if animation.duration_ms() != duration || options.loop_count.is_some() {
+                    logger.log(format!("Retimed {} to {} ms, loop count {}", input_path.display(), animation.duration_ms(), animation.loop_count));
+                }
+                let source_metadata = Metadata::read(&data);
+                let source_icc = source_metadata.icc.clone();
+                let (mut metadata, scrubbed) = source_metadata.filtered(options.metadata);
+                if !scrubbed.is_empty() {
+                    logger.log(format!("Scrubbed sensitive metadata from {}: {}", input_path.display(), scrubbed.join(", ")));
+                    detail.scrubbed = scrubbed.iter().map(|field| field.to_string()).collect();
+                    notify(ConversionUpdate::MetadataScrubbed(index, detail.scrubbed.clone()));
+                }
+                let animation = match color_conversion(source_icc, options.color_management, &mut metadata, input_path, &logger) {
+                    Some(profile) => {
+                        let (converted, convert_duration) = measure_time(|| {
+                            let frames = animation.frames.into_iter().map(|frame| Frame {
+                                image: profile.convert_to_srgb(DynamicImage::ImageRgba8(frame.image)).into_rgba8(),
+                                delay_ms: frame.delay_ms,
+                            }).collect();
+                            Animation { frames, loop_count: animation.loop_count }
+                        });
+                        logger.log(format!("Converted {} frames of {} from {} to sRGB (took {:?})", frame_count, input_path.display(), profile.description, convert_duration));
+                        detail.color_conversion = Some(format!("{} -> sRGB", profile.description));
+                        notify(ConversionUpdate::ColorConverted(index, format!("{} -> sRGB", profile.description)));
+                        converted
+                    }
+                    None => animation,
+                };
+
+                let original_dimensions = animation.dimensions();
+                let animation = if options.resize_enabled {
+                    logger.log(format!("Resizing {} frames ({})", frame_count, options.resize_mode));
+                    let ((resized, crop, linear_light), resize_duration) = measure_time(|| resize_animation(animation, options));
+                    logger.log(format!("Resizing frames took {:?}", resize_duration));
+                    if let Some(crop) = crop {
+                        logger.log(format!("Crop ({}) for {}: {}", options.resize_mode, input_path.display(), crop));
+                        detail.crop = Some(crop);
+                        notify(ConversionUpdate::Cropped(index, crop));
+                    }
+                    if let Some(linear) = linear_light {
+                        logger.log(format!("Resampled with {}{}", options.resize_filter, if linear { " in linear light" } else { "" }));
+                        detail.resize_filter = Some((options.resize_filter, linear));
+                        notify(ConversionUpdate::FilterUsed(index, options.resize_filter, linear));
+                    }
+                    resized
+                } else {
+                    animation
+                };
+                let dimensions = animation.dimensions();
+                logger.log(format!("Dimensions: {}x{} -> {}x{}", original_dimensions.0, original_dimensions.1, dimensions.0, dimensions.1));
+                detail.original_dimensions = Some(original_dimensions);
+                detail.output_dimensions = Some(dimensions);
+                notify(ConversionUpdate::Dimensions(index, original_dimensions, dimensions));
+                if !options.srcset_widths.is_empty() {
+                    logger.log(format!("Skipping srcset widths for {}, animations are written at one size", input_path.display()));
+                }
+
+                let (animation, pixel_conversion) = encodable_animation(animation, options);
+                if !pixel_conversion.is_empty() {
+                    logger.log(format!("Pixel conversion for {}: {}", input_path.display(), pixel_conversion.join(", ")));
+                    detail.pixel_conversion = pixel_conversion.clone();
+                    notify(ConversionUpdate::PixelsConverted(index, pixel_conversion));
+                }
+
+                let mut outputs = Vec::new();
+                for &(name, encoder) in &selected {
+                    let result = match encoder {
+                        Some(encoder) => write_animation(&animation, encoder, input_path, &metadata, job, &logger),
+                        None => Err(format!("No encoder registered as {}", name)),
+                    };
+                    let mut output = FormatOutput::new(encoder.map_or(name, |encoder| encoder.name()), None, original_size, result);
+                    output.dimensions = Some(dimensions);
+                    if let Some(error) = output.error_message.as_ref().filter(|_| output.status == "Conversion failed") {
+                        logger.log(format!("Error: {}: {}", output.label(), error));
+                    }
+                    notify(ConversionUpdate::FormatProcessed(index, output.clone()));
+                    outputs.push(output);
+                }
+                Ok(outputs)
+            }
+            (Ok(Loaded { image: img, data, animation: None, vector_size, .. }), None) => {
+                let orientation = metadata::read_exif(&data).and_then(metadata::exif_orientation);
+                let img = match orientation {
+                    Some(orientation) if options.auto_orient && orientation != Orientation::Normal => {
+                        logger.log(format!("EXIF orientation for {}: {}, corrected", input_path.display(), orientation));
+                        detail.orientation = Some(orientation);
+                        notify(ConversionUpdate::Oriented(index, orientation));
+                        orientation.apply(img)
+                    }
+                    _ => img,
+                };
+                let source_metadata = Metadata::read(&data);
+                let source_icc = source_metadata.icc.clone();
+                let (mut metadata, scrubbed) = source_metadata.filtered(options.metadata);
+                if !scrubbed.is_empty() {
+                    logger.log(format!("Scrubbed sensitive metadata from {}: {}", input_path.display(), scrubbed.join(", ")));
+                    detail.scrubbed = scrubbed.iter().map(|field| field.to_string()).collect();
+                    notify(ConversionUpdate::MetadataScrubbed(index, detail.scrubbed.clone()));
+                }
+                if let (Some(exif), Some(_)) = (&mut metadata.exif, detail.orientation) {
+                    metadata::reset_orientation(exif);
+                }
+                let img = match color_conversion(source_icc, options.color_management, &mut metadata, input_path, &logger) {
+                    Some(profile) => {
+                        let (converted, convert_duration) = measure_time(|| profile.convert_to_srgb(img));
+                        logger.log(format!("Converted {} from {} to sRGB (took {:?})", input_path.display(), profile.description, convert_duration));
+                        detail.color_conversion = Some(format!("{} -> sRGB", profile.description));
+                        notify(ConversionUpdate::ColorConverted(index, format!("{} -> sRGB", profile.description)));
+                        converted
+                    }
+                    None => img,
+                };
+                if !metadata.is_empty() {
+                    logger.log(format!("Keeping metadata for {}: {}", input_path.display(), metadata.summary()));
+                }
+                if let Some((width, height)) = vector_size {
+                    logger.log(format!("Rasterized {} ({}x{}) at {}x{}", input_path.display(), width, height, img.width(), img.height()));
+                }
+                let original_dimensions = vector_size.unwrap_or(img.dimensions());
+                let premultiplied = resize::uses_premultiplied_alpha(options, &img);
+                let source_color = img.color();
+                // Linear-light flag of the resamples done so far, `None` until the pixels are resampled
+                let mut resampled = None;
+
+                // Vector sources are already rasterized at the output size, short of the crop
+                let img = if options.resize_enabled && (vector_size.is_none() || options.resize_mode.crops()) {
+                    logger.log(format!("Resizing image ({})", options.resize_mode));
+                    let (resized, resize_duration) = measure_time(|| resize::resize(img, options));
+                    logger.log(format!("Resizing image took {:?}", resize_duration));
+                    if let Some(crop) = resized.crop {
+                        logger.log(format!("Crop ({}) for {}: {}", options.resize_mode, input_path.display(), crop));
+                        detail.crop = Some(crop);
+                        notify(ConversionUpdate::Cropped(index, crop));
+                    }
+                    resampled = resized.linear_light;
+                    resized.image
+                } else {
+                    img
+                };
+                logger.log(format!("Dimensions: {}x{} -> {}x{}", original_dimensions.0, original_dimensions.1, img.width(), img.height()));
+                detail.original_dimensions = Some(original_dimensions);
+                detail.output_dimensions = Some(img.dimensions());
+                notify(ConversionUpdate::Dimensions(index, original_dimensions, img.dimensions()));
+
+                // One image per srcset width (or just the one), each encoded in every format
+                let variants: Vec<(Option<u32>, DynamicImage)> = if options.srcset_widths.is_empty() {
+                    vec![(None, img)]
+                } else {
+                    let widths = srcset_targets(&options.srcset_widths, img.width());
+                    let skipped: Vec<u32> = options.srcset_widths.iter().copied().filter(|&width| width > img.width()).collect();
+                    if !skipped.is_empty() {
+                        logger.log(format!("Skipping srcset widths {:?}, larger than the {}px source", skipped, img.width()));
+                    }
+                    widths.into_iter().map(|width| {
+                        let variant = if width == img.width() {
+                            img.clone()
+                        } else {
+                            let height = ((img.height() as f64 * width as f64 / img.width() as f64).round() as u32).max(1);
+                            let (resized_img, resize_duration) = measure_time(|| resize::resize_exact(&img, width, height, options));
+                            logger.log(format!("Resizing {}w variant took {:?}", width, resize_duration));
+                            let linear = resize::uses_linear_light(options, img.dimensions(), (width, height));
+                            resampled = Some(resampled.unwrap_or(false) || linear);
+                            resized_img
+                        };
+                        (Some(width), variant)
+                    }).collect()
+                };
+
+                if let Some(linear) = resampled {
+                    logger.log(format!("Resampled with {}{}", options.resize_filter, if linear { " in linear light" } else { "" }));
+                    detail.resize_filter = Some((options.resize_filter, linear));
+                    notify(ConversionUpdate::FilterUsed(index, options.resize_filter, linear));
+                }
+
+                // Encoders take 8-bit RGB(A) only
+                let mut pixel_conversion = Vec::new();
+                if resampled.is_some() && premultiplied {
+                    pixel_conversion.push("Resized with premultiplied alpha".to_string());
+                }
+                let variants: Vec<(Option<u32>, DynamicImage)> = variants.into_iter().map(|(width, img)| {
+                    let (img, steps) = pixel_format::to_encodable(img, source_color, options);
+                    for step in steps {
+                        if !pixel_conversion.contains(&step) {
+                            pixel_conversion.push(step);
+                        }
+                    }
+                    (width, img)
+                }).collect();
+                if !pixel_conversion.is_empty() {
+                    logger.log(format!("Pixel conversion for {}: {}", input_path.display(), pixel_conversion.join(", ")));
+                    detail.pixel_conversion = pixel_conversion.clone();
+                    notify(ConversionUpdate::PixelsConverted(index, pixel_conversion));
+                }
+
+                // Decoded and resized once; every format is encoded from the same pixels
+                let mut outputs = Vec::new();
+                for (width, img) in &variants {
+                    for &(name, encoder) in &selected {
+                        let result = match encoder {
+                            Some(encoder) => write_format(img, encoder, input_path, *width, &metadata, job, &logger),
+                            None => Err(format!("No encoder registered as {}", name)),
+                        };
+                        let mut output = FormatOutput::new(encoder.map_or(name, |encoder| encoder.name()), *width, original_size, result);
+                        output.dimensions = Some(img.dimensions());
+                        if let Some(error) = output.error_message.as_ref().filter(|_| output.status == "Conversion failed") {
+                            logger.log(format!("Error: {}: {}", output.label(), error));
+                        }
+                        notify(ConversionUpdate::FormatProcessed(index, output.clone()));
+                        outputs.push(output);
+                    }
+                }
+                // Group by format, widest variant first
+                outputs.sort_by_key(|output| selected.iter().position(|&(name, _)| name.eq_ignore_ascii_case(&output.format)));
+
+                if !options.srcset_widths.is_empty() {
+                    for &(name, encoder) in &selected {
+                        let Some(encoder) = encoder else { continue };
+                        let mut candidates: Vec<u32> = outputs.iter()
+                            .filter(|output| output.format == encoder.name() && output.compressed_size.is_some())
+                            .filter_map(|output| output.width)
+                            .collect();
+                        candidates.sort_unstable();
+                        let srcset = candidates.iter()
+                            .map(|&width| format!("{} {}w", output_file_name(job, input_path, encoder.extension(), Some(width)), width))
+                            .collect::<Vec<_>>()
+                            .join(", ");
+                        logger.log(format!("srcset ({}) for {}: {}", name, input_path.display(), srcset));
+                        notify(ConversionUpdate::SrcsetGenerated(index, encoder.name().to_string(), srcset.clone()));
+                        detail.srcsets.push((encoder.name().to_string(), srcset));
+                    }
+                }
+                Ok(outputs)
+            }
+        };
+
+        match result {
+            Ok(outputs) => {
+                // The flat fields describe the first requested format
+                let primary = &outputs[0];
+                detail.compressed_size = primary.compressed_size;
+                detail.compression_rate = primary.compression_rate;
+                detail.encoding_mode = primary.encoding_mode;
+                detail.mode_reason = primary.mode_reason.clone();
+                detail.quality = primary.quality;
+                detail.measured_metric = primary.measured_metric;
+                detail.fidelity = primary.fidelity;
+                detail.output_format = Some(primary.format.clone());
+
+                let failed: Vec<&FormatOutput> = outputs.iter().filter(|output| output.status == "Conversion failed").collect();
+                let warned = outputs.iter().find(|output| output.status != "Conversion successful");
+                if !failed.is_empty() {
+                    detail.status = "Conversion failed".to_string();
+                    detail.error_message = Some(failed.iter()
+                        .map(|output| format!("{}: {}", output.format, output.error_message.as_deref().unwrap_or_default()))
+                        .collect::<Vec<_>>()
+                        .join("; "));
+                } else if let Some(output) = warned {
+                    detail.status = output.status.clone();
+                    detail.error_message = output.error_message.clone();
+                } else {
+                    detail.status = "Conversion successful".to_string();
+                }
+
+                if let Some(compressed_size) = primary.compressed_size {
+                    original_sizes.lock().push(original_size);
+                    compressed_sizes.lock().push(compressed_size);
+                    let total_original: f64 = original_sizes.lock().iter().sum::<u64>() as f64 / (1024.0 * 1024.0);
+                    let total_compressed: f64 = compressed_sizes.lock().iter().sum::<u64>() as f64 / (1024.0 * 1024.0);
+
+                    match (primary.encoding_mode, primary.mode_reason.clone()) {
+                        (Some(mode), Some(reason)) => notify(ConversionUpdate::AutoDecision(index, mode, reason)),
+                        (Some(mode), None) => notify(ConversionUpdate::EncodingUsed(index, mode)),
+                        (None, _) => {}
+                    }
+                    if let Some(quality) = primary.quality {
+                        notify(ConversionUpdate::QualityUsed(index, quality));
+                    }
+                    if let Some((metric, value)) = primary.measured_metric {
+                        notify(ConversionUpdate::MetricMeasured(index, metric, value));
+                    }
+                    if let Some(metrics) = primary.fidelity {
+                        notify(ConversionUpdate::FidelityMeasured(index, metrics));
+                    }
+                    notify(ConversionUpdate::ResultsUpdate(total_original, total_compressed));
+                }
+                notify(ConversionUpdate::StatusUpdate(index, detail.status.clone(), detail.error_message.clone()));
+                detail.outputs = outputs;
+            }
+            Err(error_msg) => {
+                logger.log(format!("Error: {}", error_msg)); // This ensures the word "error" is present for red coloring in the log
+                detail.status = "Conversion failed".to_string();
+                detail.error_message = Some(error_msg);
+                notify(ConversionUpdate::StatusUpdate(index, detail.status.clone(), detail.error_message.clone()));
+            }
+        }
+
+        notify(ConversionUpdate::ImageProcessed(index, detail.compressed_size, detail.compression_rate));
+
+        let completed = completed.fetch_add(1, Ordering::SeqCst) + 1;
+        logger.log(format!("Converting image {} of {}", completed, total_files));
+        logger.log(get_memory_usage());
+        notify(ConversionUpdate::Progress(completed, total_files));
+
+        detail
+    }).collect();
+
+    notify(ConversionUpdate::Completed);
+
+    let total_duration = start_time.elapsed();
+    logger.log(format!("Conversion process completed in {:?}", total_duration));
+
+    details
+}
+
+/// Writes every frame of each input of `job` as a PNG into its output directory; stills count as one frame.
+pub fn extract_frames(
+    job: &ConversionJob,
+    log_messages: Arc<Mutex<Vec<String>>>,
+    sender: Option<&Sender<ConversionUpdate>>,
+) -> Vec<ImageDetail> {
+    let logger = Logger::new(log_messages);
+    let notify = |update: ConversionUpdate| {
+        if let Some(sender) = sender {
+            sender.send(update).unwrap_or_default();
+        }
+    };
+
+    let input_files = &job.input_files;
+    let options = &job.options;
+    let total_files = input_files.len();
+    logger.log(format!("Extracting frames from {} files", total_files));
+    let collisions = output_collisions(job);
+    let completed = AtomicUsize::new(0);
+    let details = input_files.par_iter().enumerate().map(|(index, input_path)| {
+        let original_size = std::fs::metadata(input_path).map(|m| m.len()).unwrap_or(0);
+        let mut detail = ImageDetail::new(
+            input_path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            original_size,
+        );
+        notify(ConversionUpdate::StatusUpdate(index, "Processing...".to_string(), None));
+
+        let loaded = match collisions[index] {
+            Some(first) => Err(collision_error(first)),
+            None => load_image(input_path, options).map_err(|e| format!("Failed to load: {}", e)),
+        };
+        let result = loaded.and_then(|loaded| {
+            detail.source_format = Some(loaded.format.to_string());
+            notify(ConversionUpdate::SourceFormat(index, loaded.format.to_string()));
+            let animation = match loaded.animation {
+                Some(animation) => {
+                    detail.frame_count = Some(animation.frames.len());
+                    detail.duration_ms = Some(animation.duration_ms());
+                    notify(ConversionUpdate::Animated(index, animation.frames.len(), animation.duration_ms()));
+                    animation
+                }
+                None => Animation { frames: vec![Frame { image: loaded.image.into_rgba8(), delay_ms: 0 }], loop_count: 1 },
+            };
+            detail.original_dimensions = Some(animation.dimensions());
+            notify(ConversionUpdate::Dimensions(index, animation.dimensions(), animation.dimensions()));
+
+            let stem = output_stem(job, input_path);
+            let folder = job.output_folder(input_path);
+            std::fs::create_dir_all(&folder).map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+            for (number, frame) in animation.frames.iter().enumerate() {
+                let output_path = folder.join(format!("{}-frame-{:03}.png", stem, number + 1));
+                frame.image.save_with_format(&output_path, ImageFormat::Png)
+                    .map_err(|e| format!("Failed to save {}: {}", output_path.display(), e))?;
+                logger.log(format!("Saved frame {} of {} ({} ms) to {}", number + 1, animation.frames.len(), frame.delay_ms, output_path.display()));
+            }
+            Ok(animation.frames.len())
+        });
+
+        match result {
+            Ok(frame_count) => {
+                logger.log(format!("Extracted {} frames from {}", frame_count, input_path.display()));
+                detail.status = "Frames extracted".to_string();
+            }
+            Err(error_msg) => {
+                logger.log(format!("Error: {}", error_msg));
+                detail.status = "Conversion failed".to_string();
+                detail.error_message = Some(error_msg);
+            }
+        }
+        notify(ConversionUpdate::StatusUpdate(index, detail.status.clone(), detail.error_message.clone()));
+        notify(ConversionUpdate::ImageProcessed(index, None, None));
+        let completed = completed.fetch_add(1, Ordering::SeqCst) + 1;
+        notify(ConversionUpdate::Progress(completed, total_files));
+        detail
+    }).collect();
+
+    notify(ConversionUpdate::Completed);
+    details
+}
+
+/// Encodes `img` with `encoder`, saves it and optionally measures it against `img`.
+/// `width` names the output as a srcset variant.
+fn write_format(
+    img: &DynamicImage,
+    encoder: &dyn ImageEncoder,
+    input_path: &Path,
+    width: Option<u32>,
+    metadata: &Metadata,
+    job: &ConversionJob,
+    logger: &Logger,
+) -> Result<(u64, EncodeReport, Option<FidelityMetrics>), String> {
+    let options = &job.options;
+    let format = encoder.name();
+    logger.log(format!("Encoding to {}", format));
+    let sized_encoder = encoder.leaving_room_for(metadata);
+    let (encode_result, encode_duration) = measure_time(|| sized_encoder.encode_with_report(img));
+    logger.log(format!("Encoding to {} took {:?}", format, encode_duration));
+
+    let (encoded_data, mut report) = encode_result.map_err(|e| format!("Failed to encode: {}", e))?;
+    logger.log(format!("{} encoding successful", format));
+    if let (Some(mode), Some(reason)) = (report.mode, &report.reason) {
+        logger.log(format!("Auto mode chose {}: {}", mode, reason));
+    }
+    if let Some(warning) = &report.warning {
+        logger.log(format!("Warning: {}", warning.message));
+    }
+    let (encoded_data, metadata_dropped) = save_encoded(encoded_data, encoder, input_path, width, metadata, job, logger)?;
+    if let Some(message) = metadata_dropped {
+        report.warn("Metadata dropped", message);
+    }
+
+    let fidelity = options.compute_metrics.then(|| {
+        let (metrics_result, metrics_duration) = measure_time(|| measure_fidelity(encoder, img, &encoded_data));
+        record_fidelity(metrics_result, metrics_duration, format, &mut report, logger)
+    }).flatten();
+    Ok((encoded_data.len() as u64, report, fidelity))
+}
+
+/// Fidelity of `encoded_data` against `img` as the encoder saw it.
+fn measure_fidelity(encoder: &dyn ImageEncoder, img: &DynamicImage, encoded_data: &[u8]) -> Result<FidelityMetrics, String> {
+    let decoded = encoder.decode(encoded_data).map_err(|e| e.to_string())?;
+    Ok(FidelityMetrics::measure(&encoder.fidelity_reference(img), &decoded))
+}
+
+/// Worst fidelity across the frames of an animated `encoded_data`, each against its source frame.
+fn measure_animation_fidelity(encoder: &dyn ImageEncoder, animation: &Animation, encoded_data: &[u8]) -> Result<FidelityMetrics, String> {
+    let decoded = encoder.decode_animation(encoded_data).map_err(|e| e.to_string())?;
+    let sources = animation.distinct_frames();
+    if decoded.frames.len() != sources.len() {
+        return Err(format!("decoded {} frames, expected {}", decoded.frames.len(), sources.len()));
+    }
+    let worst = FidelityMetrics { psnr: f64::INFINITY, ssim: 1.0, ms_ssim: 1.0 };
+    Ok(decoded.frames.into_iter().zip(sources).fold(worst, |worst, (frame, source)| {
+        let source = DynamicImage::ImageRgba8(source.clone());
+        let metrics = FidelityMetrics::measure(&encoder.fidelity_reference(&source), &DynamicImage::ImageRgba8(frame.image));
+        FidelityMetrics { psnr: worst.psnr.min(metrics.psnr), ssim: worst.ssim.min(metrics.ssim), ms_ssim: worst.ms_ssim.min(metrics.ms_ssim) }
+    }))
+}
+
+/// Logs a fidelity measurement, flagging the output when it couldn't be taken.
+fn record_fidelity(
+    result: Result<FidelityMetrics, String>,
+    duration: std::time::Duration,
+    format: &str,
+    report: &mut EncodeReport,
+    logger: &Logger,
+) -> Option<FidelityMetrics> {
+    match result {
+        Ok(metrics) => {
+            logger.log(format!("Fidelity: PSNR {:.2} dB, SSIM {:.4}, MS-SSIM {:.4} (took {:?})", metrics.psnr, metrics.ssim, metrics.ms_ssim, duration));
+            Some(metrics)
+        }
+        Err(e) => {
+            logger.log(format!("Error measuring fidelity: {}", e));
+            report.warn("Metrics unavailable", format!("Metrics unavailable for {}: {}", format, e));
+            None
+        }
+    }
+}
+
+/// Encodes every frame of `animation` with `encoder` and saves it; encoders without animation support get the
+/// first frame, flagged on the output's status.
+fn write_animation(
+    animation: &Animation,
+    encoder: &dyn ImageEncoder,
+    input_path: &Path,
+    metadata: &Metadata,
+    job: &ConversionJob,
+    logger: &Logger,
+) -> Result<(u64, EncodeReport, Option<FidelityMetrics>), String> {
+    let format = encoder.name();
+    logger.log(format!("Encoding {} frames to {}", animation.frames.len(), format));
+    let sized_encoder = encoder.leaving_room_for(metadata);
+    let (encode_result, encode_duration) = measure_time(|| match sized_encoder.encode_animation(animation) {
+        Err(ImageError::Unsupported(_)) => {
+            let first = DynamicImage::ImageRgba8(animation.frames[0].image.clone());
+            sized_encoder.encode_with_report(&first).map(|(data, mut report)| {
+                report.warning = Some(EncodeWarning {
+                    status: "First frame only",
+                    message: format!("{} output can't hold an animation, only the first of {} frames was written", format, animation.frames.len()),
+                });
+                (data, report)
+            })
+        }
+        result => result,
+    });
+    logger.log(format!("Encoding to {} took {:?}", format, encode_duration));
+
+    let (encoded_data, mut report) = encode_result.map_err(|e| format!("Failed to encode: {}", e))?;
+    logger.log(format!("{} encoding successful", format));
+    if let (Some(mode), Some(reason)) = (report.mode, &report.reason) {
+        logger.log(format!("Auto mode chose {}: {}", mode, reason));
+    }
+    if let Some(warning) = &report.warning {
+        logger.log(format!("Warning: {}", warning.message));
+    }
+    let first_frame_only = report.warning.as_ref().is_some_and(|warning| warning.status == "First frame only");
+    let (encoded_data, metadata_dropped) = save_encoded(encoded_data, encoder, input_path, None, metadata, job, logger)?;
+    if let Some(message) = metadata_dropped {
+        report.warn("Metadata dropped", message);
+    }
+
+    let fidelity = job.options.compute_metrics.then(|| {
+        let (metrics_result, metrics_duration) = measure_time(|| if first_frame_only {
+            measure_fidelity(encoder, &DynamicImage::ImageRgba8(animation.frames[0].image.clone()), &encoded_data)
+        } else {
+            measure_animation_fidelity(encoder, animation, &encoded_data)
+        });
+        record_fidelity(metrics_result, metrics_duration, format, &mut report, logger)
+    }).flatten();
+    Ok((encoded_data.len() as u64, report, fidelity))
+}
+
+/// Adds `metadata` to `encoded_data` where the encoder supports it and writes the result next to the other outputs.
+/// Also returns why the metadata was left out, when the encoder couldn't hold it.
+fn save_encoded(
+    encoded_data: Vec<u8>,
+    encoder: &dyn ImageEncoder,
+    input_path: &Path,
+    width: Option<u32>,
+    metadata: &Metadata,
+    job: &ConversionJob,
+    logger: &Logger,
+) -> Result<(Vec<u8>, Option<String>), String> {
+    let format = encoder.name();
+    let (encoded_data, metadata_dropped) = if metadata.is_empty() {
+        (encoded_data, None)
+    } else {
+        match encoder.embed_metadata(&encoded_data, metadata) {
+            Ok(with_metadata) => (with_metadata, None),
+            Err(e) => {
+                let message = format!("Metadata not written: {}", e);
+                logger.log(message.clone());
+                (encoded_data, Some(message))
+            }
+        }
+    };
+
+    let output_path = job.output_folder(input_path).join(output_file_name(job, input_path, encoder.extension(), width));
+    // JPEG output next to a JPEG source would otherwise replace it
+    if output_path.canonicalize().ok() == Some(input_path.canonicalize().unwrap_or_default()) {
+        return Err(format!("Output {} would overwrite the input", output_path.display()));
+    }
+
+    logger.log(format!("Saving {} file to: {}", format, output_path.display()));
+    let (save_result, save_duration) = measure_time(|| save_output(&encoded_data, &output_path));
+    logger.log(format!("Saving {} file took {:?}", format, save_duration));
+    save_result.map_err(|e| format!("Failed to save: {}", e))?;
+    logger.log(format!("{} file saved successfully", format));
+    Ok((encoded_data, metadata_dropped))
+}
+
+/// Applies `color_management` to a source tagged with `icc`, setting the profile `metadata` embeds; returns the
+/// profile to convert the pixels to sRGB from, when they need converting.
+fn color_conversion(
+    icc: Option<Vec<u8>>,
+    color_management: ColorManagement,
+    metadata: &mut Metadata,
+    input_path: &Path,
+    logger: &Logger,
+) -> Option<IccProfile> {
+    match (icc, color_management) {
+        (Some(icc), ColorManagement::ConvertToSrgb) => match IccProfile::parse(&icc) {
+            Ok(profile) if profile.is_srgb() => {
+                logger.log(format!("ICC profile of {} is sRGB ({}), no conversion needed", input_path.display(), profile.description));
+                None
+            }
+            Ok(profile) => {
+                // The pixels are about to be sRGB, which untagged outputs already mean
+                metadata.icc = None;
+                Some(profile)
+            }
+            Err(e) => {
+                // Embedding the profile at least keeps colors right in color-managed viewers
+                logger.log(format!("Error: ICC profile of {} not converted, embedding it instead: {}", input_path.display(), e));
+                metadata.icc = Some(icc);
+                None
+            }
+        },
+        (Some(icc), ColorManagement::PreserveProfile) => {
+            logger.log(format!("Preserving the ICC profile of {}", input_path.display()));
+            metadata.icc = Some(icc);
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Output name for `input_path`: either the rename target or the input stem, with the encoder's extension.
+/// Srcset variants get a `-<width>w` suffix, e.g. `photo-640w.webp`.
+pub fn output_file_name(job: &ConversionJob, input_path: &Path, extension: &str, width: Option<u32>) -> String {
+    let stem = output_stem(job, input_path);
+    match width {
+        Some(width) => format!("{}-{}w.{}", stem, width, extension),
+        None => format!("{}.{}", stem, extension),
+    }
+}
+
+/// The rename target, or the input's file stem.
+/// Jobs with several inputs number the rename target by input, e.g. `holiday-1`, `holiday-2`.
+fn output_stem(job: &ConversionJob, input_path: &Path) -> String {
+    let options = &job.options;
+    if !options.rename_enabled || options.output_filename.is_empty() {
+        return input_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    }
+    match job.input_files.iter().position(|path| path == input_path) {
+        Some(position) if job.input_files.len() > 1 => format!("{}-{}", options.output_filename, position + 1),
+        _ => options.output_filename.clone(),
+    }
+}
+
+/// For each input of `job`, the earlier input whose outputs would land on the same paths, if any.
+fn output_collisions(job: &ConversionJob) -> Vec<Option<&Path>> {
+    let mut first_inputs = HashMap::new();
+    job.input_files.iter().map(|input_path| {
+        let stem = job.output_folder(input_path).join(output_stem(job, input_path));
+        let first = *first_inputs.entry(stem).or_insert(input_path.as_path());
+        (first != input_path.as_path()).then_some(first)
+    }).collect()
+}
+
+fn collision_error(first: &Path) -> String {
+    format!("Skipped, its outputs would overwrite those of {}", first.display())
+}
+
+/// Srcset widths that don't upscale a `source_width` image, widest first.
+/// Falls back to the source width when every requested width is too large.
+fn srcset_targets(widths: &[u32], source_width: u32) -> Vec<u32> {
+    let mut targets: Vec<u32> = widths.iter().copied().filter(|&width| width > 0 && width <= source_width).collect();
+    targets.sort_unstable_by(|a, b| b.cmp(a));
+    targets.dedup();
+    if targets.is_empty() {
+        targets.push(source_width);
+    }
+    targets
+}
+
+/// A decoded input file.
+struct Loaded {
+    image: DynamicImage,
+    /// The file's bytes, for its metadata.
+    data: Vec<u8>,
+    /// Display name of the source format, e.g. "JPEG".
+    format: &'static str,
+    /// Every frame of sources with more than one, `image` being the first.
+    animation: Option<Animation>,
+    /// Intrinsic size of a vector source, which comes rasterized at `resize::raster_size`.
+    vector_size: Option<(u32, u32)>,
+}
+
+/// Decodes the file at `path`, trusting its magic bytes over its extension; SVGs are rasterized per `options`.
+/// Sources with more than one frame also come back as an `Animation`, the image being the first frame.
+fn load_image(path: &Path, options: &ConversionOptions) -> Result<Loaded, ImageError> {
+    let data = std::fs::read(path)?;
+    let mut reader = ImageReader::new(Cursor::new(&data));
+    // The extension only counts for formats without a signature, such as TGA
+    if let Ok(format) = ImageFormat::from_path(path) {
+        reader.set_format(format);
+    }
+    let reader = reader.with_guessed_format()?;
+    if reader.format().is_none() && is_svg(&data) {
+        let (image, intrinsic) = rasterize_svg(&data, path, options)?;
+        return Ok(Loaded { image: DynamicImage::ImageRgba8(image), data, format: "SVG", animation: None, vector_size: Some(intrinsic) });
+    }
+    let format = reader.format().ok_or_else(|| {
+        ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+            ImageFormatHint::PathExtension(path.to_path_buf()),
+            UnsupportedErrorKind::Format(ImageFormatHint::Unknown),
+        ))
+    })?;
+    if let Some(animation) = decode_animation(&data, format)? {
+        if let Some(first) = animation.frames.first() {
+            let image = DynamicImage::ImageRgba8(first.image.clone());
+            let animation = (animation.frames.len() > 1).then_some(animation);
+            return Ok(Loaded { image, data, format: format_name(format), animation, vector_size: None });
+        }
+    }
+    let image = reader.decode()?;
+    Ok(Loaded { image, data, format: format_name(format), animation: None, vector_size: None })
+}
+
+/// Renders an SVG document at `resize::raster_size` when resizing, or at its intrinsic size, returning the
+/// pixels and that intrinsic size. Relative image references resolve against the file's folder.
+fn rasterize_svg(data: &[u8], path: &Path, options: &ConversionOptions) -> Result<(RgbaImage, (u32, u32)), ImageError> {
+    let svg_error = |e: String| ImageError::Decoding(DecodingError::new(ImageFormatHint::Name("SVG".to_string()), e));
+    let usvg_options = usvg::Options { resources_dir: path.parent().map(Path::to_path_buf), ..usvg::Options::default() };
+    let tree = usvg::Tree::from_data(data, &usvg_options).map_err(|e| svg_error(e.to_string()))?;
+    let intrinsic = (tree.size().width().ceil() as u32, tree.size().height().ceil() as u32);
+    let (width, height) = if options.resize_enabled { resize::raster_size(intrinsic, options) } else { intrinsic };
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| svg_error(format!("cannot rasterize at {}x{}", width, height)))?;
+    let transform = tiny_skia::Transform::from_scale(width as f32 / tree.size().width(), height as f32 / tree.size().height());
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+    // tiny-skia keeps premultiplied alpha, the rest of the pipeline straight alpha
+    let pixels = pixmap.pixels().iter().flat_map(|pixel| {
+        let color = pixel.demultiply();
+        [color.red(), color.green(), color.blue(), color.alpha()]
+    }).collect();
+    let image = RgbaImage::from_raw(width, height, pixels).ok_or_else(|| svg_error("pixel buffer size mismatch".to_string()))?;
+    Ok((image, intrinsic))
+}
+
+/// Whether `data` is an SVG document: an `<svg` root within the first KB, after any XML prolog.
+fn is_svg(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(1024)]);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<!--") || head.starts_with("<!DOCTYPE") || head.starts_with("<svg")) && head.contains("<svg")
+}
+
+/// Every frame of `data` for the formats that can animate, `None` for the rest (and for still WebPs).
+fn decode_animation(data: &[u8], format: ImageFormat) -> Result<Option<Animation>, ImageError> {
+    match format {
+        ImageFormat::Gif => Animation::decode_gif(data).map(Some),
+        ImageFormat::WebP if Animation::is_animated_webp(data) => Animation::decode_webp(data).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Resizes every frame like `resize::resize`; all frames keep the first frame's crop window, so smart crops
+/// don't jump between frames. Also returns that window and the linear-light flag of the resample, if any.
+fn resize_animation(animation: Animation, options: &ConversionOptions) -> (Animation, Option<CropRect>, Option<bool>) {
+    let (mut crop, mut linear_light, mut size) = (None, None, None);
+    let frames = animation.frames.into_iter().map(|frame| {
+        let img = DynamicImage::ImageRgba8(frame.image);
+        let image = match size {
+            None => {
+                let resized = resize::resize(img, options);
+                crop = resized.crop;
+                linear_light = resized.linear_light;
+                size = Some(resized.image.dimensions());
+                resized.image
+            }
+            Some((width, height)) => {
+                let img = match crop {
+                    Some(crop) => img.crop_imm(crop.x, crop.y, crop.width, crop.height),
+                    None => img,
+                };
+                if img.dimensions() == (width, height) { img } else { resize::resize_exact(&img, width, height, options) }
+            }
+        };
+        Frame { image: image.into_rgba8(), delay_ms: frame.delay_ms }
+    }).collect();
+    (Animation { frames, loop_count: animation.loop_count }, crop, linear_light)
+}
+
+/// Applies `pixel_format::to_encodable` to every frame, keeping them RGBA as the animation encoder expects.
+fn encodable_animation(animation: Animation, options: &ConversionOptions) -> (Animation, Vec<String>) {
+    let mut steps = Vec::new();
+    let frames = animation.frames.into_iter().map(|frame| {
+        let (image, frame_steps) = pixel_format::to_encodable(DynamicImage::ImageRgba8(frame.image), ColorType::Rgba8, options);
+        for step in frame_steps {
+            if !steps.contains(&step) {
+                steps.push(step);
+            }
+        }
+        Frame { image: image.into_rgba8(), delay_ms: frame.delay_ms }
+    }).collect();
+    (Animation { frames, loop_count: animation.loop_count }, steps)
+}
+
+fn save_output(data: &[u8], output_path: &Path) -> std::io::Result<()> {
+    if let Some(folder) = output_path.parent() {
+        std::fs::create_dir_all(folder)?;
+    }
+    let mut file = File::create(output_path)?;
+    file.write_all(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversion::Converter;
+    use crate::encoders::{JpegEncoder, WebpEncoder};
+    use crate::metadata::MetadataPolicy;
+    use crate::resize::ResizeMode;
+    use std::path::PathBuf;
+
+    /// 24x16 canvas, red on the left half and transparent on the right.
+    const SVG: &str = r##"<?xml version="1.0"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="24" height="16" viewBox="0 0 24 16">
+  <rect width="12" height="16" fill="#ff0000"/>
+</svg>"##;
+
+    #[test]
+    fn detects_svg_by_content() {
+        assert!(is_svg(SVG.as_bytes()));
+        assert!(is_svg(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"));
+        assert!(!is_svg(b"<?xml version=\"1.0\"?><html/>"));
+        assert!(!is_svg(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    #[test]
+    fn rasterizes_svg_at_intrinsic_size_without_resize() {
+        let (image, intrinsic) = rasterize_svg(SVG.as_bytes(), Path::new("icon.svg"), &ConversionOptions::default()).unwrap();
+        assert_eq!(intrinsic, (24, 16));
+        assert_eq!(image.dimensions(), (24, 16));
+        assert_eq!(image.get_pixel(2, 8).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(20, 8).0[3], 0);
+    }
+
+    #[test]
+    fn rasterizes_svg_at_resize_target() {
+        // Vectors scale up without loss, so `never_upscale` is ignored
+        let options = ConversionOptions::new().resize(96, 96).never_upscale(true);
+        let (image, _) = rasterize_svg(SVG.as_bytes(), Path::new("icon.svg"), &options).unwrap();
+        assert_eq!(image.dimensions(), (96, 64));
+        assert_eq!(image.get_pixel(40, 32).0, [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(56, 32).0[3], 0);
+
+        let options = ConversionOptions::new().resize(10, 10).resize_mode(ResizeMode::Fill);
+        let (image, _) = rasterize_svg(SVG.as_bytes(), Path::new("icon.svg"), &options).unwrap();
+        assert_eq!(image.dimensions(), (15, 10));
+    }
+
+    #[test]
+    fn converts_svg_to_webp() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("icon.svg");
+        std::fs::write(&input, SVG).unwrap();
+        assert!(is_supported_input(&input));
+        assert_eq!(source_format_name(&input), Some("SVG"));
+
+        // Percentage is applied once, at rasterization
+        let options = ConversionOptions::new().scale(200.0);
+        let details = Converter::new().run(&ConversionJob::new(vec![input.clone()], dir.path()).options(options));
+        assert_eq!(details[0].status, "Conversion successful", "{:?}", details[0].error_message);
+        assert_eq!(details[0].source_format.as_deref(), Some("SVG"));
+        assert_eq!(details[0].original_dimensions, Some((24, 16)));
+        assert_eq!(details[0].output_dimensions, Some((48, 32)));
+        let output = image::open(dir.path().join("icon.webp")).unwrap().into_rgba8();
+        assert_eq!(output.dimensions(), (48, 32));
+        let left = output.get_pixel(8, 16).0;
+        assert!(left[0] > 240 && left[1] < 16 && left[2] < 16 && left[3] > 240, "{:?}", left);
+
+        // Fill rasterizes to cover the box, then crops without resampling
+        let options = ConversionOptions::new().resize(16, 16).resize_mode(ResizeMode::Fill).rename("square");
+        let details = Converter::new().run(&ConversionJob::new(vec![input], dir.path()).options(options));
+        assert_eq!(details[0].output_dimensions, Some((16, 16)));
+        assert_eq!(details[0].crop, Some(CropRect { x: 4, y: 0, width: 16, height: 16 }));
+        assert_eq!(image::open(dir.path().join("square.webp")).unwrap().dimensions(), (16, 16));
+    }
+
+    #[test]
+    fn mirrors_input_folders_in_the_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs: Vec<PathBuf> = ["photos/a/img.png", "photos/b/img.png", "photos/img.png"].iter().map(|path| dir.path().join(path)).collect();
+        for input in &inputs {
+            std::fs::create_dir_all(input.parent().unwrap()).unwrap();
+            RgbaImage::from_pixel(4, 4, image::Rgba([10, 20, 30, 255])).save(input).unwrap();
+        }
+        let output = dir.path().join("out");
+
+        let job = ConversionJob::new(inputs.clone(), &output).input_root(dir.path().join("photos"));
+        assert_eq!(job.output_folder(&inputs[0]), output.join("a"));
+        let details = Converter::new().run(&job);
+        assert!(details.iter().all(|detail| detail.status == "Conversion successful"), "{:?}", details.iter().map(|detail| &detail.error_message).collect::<Vec<_>>());
+        for path in ["a/img.webp", "b/img.webp", "img.webp"] {
+            assert!(output.join(path).is_file(), "{} missing", path);
+        }
+
+        let details = Converter::new().extract_frames(&job);
+        assert!(details.iter().all(|detail| detail.status == "Frames extracted"));
+        assert!(output.join("b/img-frame-001.png").is_file());
+    }
+
+    #[test]
+    fn reports_output_name_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs: Vec<PathBuf> = ["a/img.png", "b/img.png", "b/other.png"].iter().map(|path| dir.path().join(path)).collect();
+        for input in &inputs {
+            std::fs::create_dir_all(input.parent().unwrap()).unwrap();
+            RgbaImage::from_pixel(4, 4, image::Rgba([10, 20, 30, 255])).save(input).unwrap();
+        }
+
+        // Without a root both images named img.png would write out/img.webp
+        let details = Converter::new().run(&ConversionJob::new(inputs.clone(), dir.path().join("out")));
+        assert_eq!(details[0].status, "Conversion successful");
+        assert_eq!(details[1].status, "Conversion failed");
+        assert!(details[1].error_message.as_deref().unwrap_or_default().contains(&inputs[0].display().to_string()));
+        assert_eq!(details[2].status, "Conversion successful");
+    }
+
+    #[test]
+    fn numbers_renamed_outputs_when_there_are_several_inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs: Vec<PathBuf> = ["a.png", "b.png"].iter().map(|name| dir.path().join(name)).collect();
+        for input in &inputs {
+            RgbaImage::from_pixel(4, 4, image::Rgba([10, 20, 30, 255])).save(input).unwrap();
+        }
+        let output = dir.path().join("out");
+
+        let job = ConversionJob::new(inputs.clone(), &output).options(ConversionOptions::new().rename("holiday"));
+        assert_eq!(output_file_name(&job, &inputs[1], "webp", Some(640)), "holiday-2-640w.webp");
+        assert!(output_collisions(&job).iter().all(Option::is_none));
+        let details = Converter::new().run(&job);
+        assert!(details.iter().all(|detail| detail.status == "Conversion successful"), "{:?}", details.iter().map(|detail| &detail.error_message).collect::<Vec<_>>());
+        assert!(output.join("holiday-1.webp").is_file() && output.join("holiday-2.webp").is_file());
+
+        // A single input keeps the name as given
+        let job = ConversionJob::new(vec![inputs[0].clone()], &output).options(ConversionOptions::new().rename("cover"));
+        assert_eq!(Converter::new().run(&job)[0].status, "Conversion successful");
+        assert!(output.join("cover.webp").is_file());
+    }
+
+    #[test]
+    fn carries_metadata_into_jpeg_and_warns_where_it_cannot() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("photo.jpg");
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(16, 16, image::Rgb([30, 120, 200])));
+        let jpeg = JpegEncoder::default();
+        let metadata = Metadata { xmp: Some(b"<x:xmpmeta/>".to_vec()), ..Default::default() };
+        std::fs::write(&input, jpeg.embed_metadata(&jpeg.encode(&img).unwrap(), &metadata).unwrap()).unwrap();
+
+        let options = ConversionOptions::new().encoders(["JPEG", "AVIF"]).metadata(MetadataPolicy::KeepAll);
+        let details = Converter::new().run(&ConversionJob::new(vec![input], dir.path().join("out")).options(options));
+        let outputs = &details[0].outputs;
+        assert_eq!(outputs[0].status, "Conversion successful", "{:?}", outputs[0].error_message);
+        assert_eq!(Metadata::read(&std::fs::read(dir.path().join("out/photo.jpg")).unwrap()), metadata);
+        // The AVIF is still written, without the metadata
+        assert_eq!(outputs[1].status, "Metadata dropped");
+        assert!(outputs[1].error_message.as_deref().unwrap().starts_with("Metadata not written"));
+        assert!(dir.path().join("out/photo.avif").exists());
+        assert_eq!(details[0].status, "Metadata dropped");
+    }
+
+    #[test]
+    fn target_size_counts_embedded_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut state = 7u32;
+        let noise = image::RgbImage::from_fn(256, 256, |_, _| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            image::Rgb([(state >> 24) as u8, (state >> 16) as u8, (state >> 8) as u8])
+        });
+        let jpeg = JpegEncoder::new(95);
+        let target = 20 * 1024;
+        let converter = Converter::new().encoders(vec![Box::new(WebpEncoder::new().target_size(target))]);
+        let options = ConversionOptions::new().metadata(MetadataPolicy::KeepAll);
+        for (name, xmp_size, status) in [("small", 8 * 1024, "Conversion successful"), ("large", 40 * 1024, "Over target size")] {
+            let input = dir.path().join(format!("{}.jpg", name));
+            let metadata = Metadata { xmp: Some(vec![b' '; xmp_size]), ..Default::default() };
+            let source = jpeg.embed_metadata(&jpeg.encode(&DynamicImage::ImageRgb8(noise.clone())).unwrap(), &metadata).unwrap();
+            std::fs::write(&input, source).unwrap();
+
+            let details = converter.run(&ConversionJob::new(vec![input], dir.path().join("out")).options(options.clone()));
+            assert_eq!(details[0].status, status, "{:?}", details[0].error_message);
+            let written = std::fs::read(dir.path().join(format!("out/{}.webp", name))).unwrap();
+            assert_eq!(Metadata::read(&written).xmp, metadata.xmp);
+            if status == "Conversion successful" {
+                assert!(written.len() as u64 <= target, "{} bytes", written.len());
+            } else {
+                assert!(details[0].error_message.as_deref().unwrap().contains("metadata alone"));
+            }
+        }
+    }
+
+    #[test]
+    fn measures_or_flags_fidelity_for_every_output() {
+        let dir = tempfile::tempdir().unwrap();
+        // Half transparent: JPEG flattens it onto white, which the reference has to match
+        let still = dir.path().join("still.png");
+        image::RgbaImage::from_fn(32, 32, |x, _| if x < 16 { image::Rgba([40, 90, 160, 255]) } else { image::Rgba([0, 0, 0, 0]) }).save(&still).unwrap();
+        let animated = dir.path().join("animated.gif");
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(std::fs::File::create(&animated).unwrap());
+            let frames = [[200, 30, 30, 255], [30, 30, 200, 255], [30, 30, 200, 255]].map(|color| {
+                image::Frame::from_parts(image::RgbaImage::from_pixel(16, 16, image::Rgba(color)), 0, 0, image::Delay::from_numer_denom_ms(100, 1))
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+
+        let options = ConversionOptions::new().encoders(["JPEG", "AVIF"]).compute_metrics(true);
+        let details = Converter::new().run(&ConversionJob::new(vec![still, animated.clone()], dir.path().join("out")).options(options));
+        let jpeg = &details[0].outputs[0];
+        assert_eq!(jpeg.status, "Conversion successful", "{:?}", jpeg.error_message);
+        assert!(jpeg.fidelity.unwrap().psnr > 30.0, "{:?}", jpeg.fidelity);
+        // AVIF output can't be decoded back in this build
+        let avif = &details[0].outputs[1];
+        assert_eq!(avif.status, "Metrics unavailable");
+        assert!(avif.error_message.as_deref().unwrap().starts_with("Metrics unavailable for AVIF"));
+        assert!(avif.fidelity.is_none() && avif.compressed_size.is_some());
+
+        // Animated outputs are measured frame by frame, the repeated frame once
+        let options = ConversionOptions::new().compute_metrics(true);
+        let converter = Converter::new().encoders(vec![Box::new(WebpEncoder::new().mode(crate::encoders::EncodingMode::Lossless))]);
+        let details = converter.run(&ConversionJob::new(vec![animated], dir.path().join("out")).options(options));
+        assert_eq!(details[0].outputs[0].status, "Conversion successful", "{:?}", details[0].outputs[0].error_message);
+        let fidelity = details[0].outputs[0].fidelity.unwrap();
+        assert_eq!(fidelity.psnr, f64::INFINITY);
+        assert!((fidelity.ssim - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn color_manages_every_frame_of_animations() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("tagged.webp");
+        let frames = [[200, 100, 50, 255], [50, 100, 200, 255]].map(|color| Frame { image: RgbaImage::from_pixel(8, 8, image::Rgba(color)), delay_ms: 100 });
+        let animation = Animation { frames: frames.to_vec(), loop_count: 0 };
+        let webp = WebpEncoder::new().mode(crate::encoders::EncodingMode::Lossless);
+        let metadata = Metadata { icc: Some(crate::color::tests::display_p3()), ..Default::default() };
+        std::fs::write(&input, webp.embed_metadata(&webp.encode_animation(&animation).unwrap().0, &metadata).unwrap()).unwrap();
+        let converter = Converter::new().encoders(vec![Box::new(webp)]);
+
+        let details = converter.run(&ConversionJob::new(vec![input.clone()], dir.path().join("srgb")));
+        assert_eq!(details[0].status, "Conversion successful", "{:?}", details[0].error_message);
+        assert_eq!(details[0].color_conversion.as_deref(), Some("Display P3 -> sRGB"));
+        let written = std::fs::read(dir.path().join("srgb/tagged.webp")).unwrap();
+        assert_eq!(Metadata::read(&written).icc, None);
+        let converted = Animation::decode_webp(&written).unwrap();
+        let profile = IccProfile::parse(metadata.icc.as_deref().unwrap()).unwrap();
+        for (frame, source) in converted.frames.iter().zip(&animation.frames) {
+            let expected = profile.convert_to_srgb(DynamicImage::ImageRgba8(source.image.clone())).into_rgba8();
+            assert_eq!(frame.image.get_pixel(0, 0), expected.get_pixel(0, 0));
+            assert_ne!(frame.image.get_pixel(0, 0), source.image.get_pixel(0, 0));
+        }
+
+        let options = ConversionOptions::new().color_management(ColorManagement::PreserveProfile);
+        let details = converter.run(&ConversionJob::new(vec![input], dir.path().join("tagged")).options(options));
+        assert_eq!(details[0].color_conversion, None);
+        let written = std::fs::read(dir.path().join("tagged/tagged.webp")).unwrap();
+        assert_eq!(Metadata::read(&written).icc, metadata.icc);
+        assert_eq!(Animation::decode_webp(&written).unwrap().frames[0].image.get_pixel(0, 0).0, [200, 100, 50, 255]);
+    }
+}
@@ -0,0 +1,25 @@
+// lib.rs
+pub mod animation;
+pub mod color;
+pub mod conversion;
+pub mod encoders;
+pub mod folder_scan;
+pub mod image_processing;
+pub mod metadata;
+pub mod metrics;
+pub mod pixel_format;
+pub mod resize;
+pub mod utils;
+
+pub use animation::Animation;
+pub use color::{ColorManagement, IccProfile};
+pub use conversion::{ConversionJob, ConversionOptions, ConversionUpdate, Converter, FormatOutput, ImageDetail};
+pub use encoders::{
+    builtin_encoders, AvifEncodeSettings, AvifEncoder, EncodeReport, EncoderOption, EncodingMode, ImageEncoder, JpegEncoder,
+    OptionKind, OptionValue, WebpEncodeSettings, WebpEncoder,
+};
+pub use folder_scan::{FolderScan, SymlinkPolicy};
+pub use metadata::{Metadata, MetadataPolicy, Orientation};
+pub use metrics::{FidelityMetrics, QualityMetric};
+pub use pixel_format::AlphaHandling;
+pub use resize::{CropRect, ResizeFilter, ResizeMode};
@@ -1,6 +1,5 @@
 // main.rs
 mod app;
-mod utils;
 
 use app::App;
 use eframe::NativeOptions;
@@ -0,0 +1,724 @@
+// metadata.rs
+use image::{DynamicImage, ImageDecoder};
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::ops::Range;
+
+/// EXIF Orientation tag in IFD0.
+const ORIENTATION_TAG: u16 = 0x0112;
+const EXIF_IFD_TAG: u16 = 0x8769;
+const GPS_IFD_TAG: u16 = 0x8825;
+/// Offset and length of the JPEG thumbnail in IFD1.
+const THUMBNAIL_OFFSET_TAG: u16 = 0x0201;
+const THUMBNAIL_LENGTH_TAG: u16 = 0x0202;
+/// Fields `MetadataPolicy::ScrubSensitive` drops besides GPS and the thumbnail, with the name reported for each.
+const SENSITIVE_TAGS: &[(u16, &str)] = &[
+    (0xA430, "owner name"),    // CameraOwnerName
+    (0xA431, "serial number"), // BodySerialNumber
+    (0xA435, "serial number"), // LensSerialNumber
+    (0xC62F, "serial number"), // CameraSerialNumber (DNG)
+    (0x927C, "maker note"),    // Vendor data, which often holds serial numbers
+];
+/// Parts of XMP property names `MetadataPolicy::ScrubSensitive` removes, with the name reported for each.
+const SENSITIVE_XMP: &[(&str, &str)] = &[
+    ("GPS", "GPS"),
+    ("SerialNumber", "serial number"),
+    ("OwnerName", "owner name"),
+    ("Thumbnails", "thumbnail"),
+];
+/// Nested IFDs followed when scrubbing, guarding against offset loops.
+const MAX_IFD_DEPTH: usize = 4;
+/// Signature opening a JPEG APP1 segment that holds XMP.
+pub(crate) const JPEG_XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// Signature opening each JPEG APP2 segment that holds part of an ICC profile.
+pub(crate) const JPEG_ICC_SIGNATURE: &[u8] = b"ICC_PROFILE\0";
+/// PNG iTXt keyword for XMP.
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+/// Which source metadata is carried into the outputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetadataPolicy {
+    KeepAll,
+    /// Everything but location, serial numbers, owner names and thumbnails; copyright, author and capture time stay.
+    ScrubSensitive,
+    /// Only the color profile, so colors still render as intended.
+    IccOnly,
+    StripAll,
+}
+
+impl MetadataPolicy {
+    pub const ALL: [MetadataPolicy; 4] = [
+        MetadataPolicy::KeepAll,
+        MetadataPolicy::ScrubSensitive,
+        MetadataPolicy::IccOnly,
+        MetadataPolicy::StripAll,
+    ];
+}
+
+impl std::fmt::Display for MetadataPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataPolicy::KeepAll => write!(f, "Keep all"),
+            MetadataPolicy::ScrubSensitive => write!(f, "Scrub sensitive"),
+            MetadataPolicy::IccOnly => write!(f, "Keep ICC only"),
+            MetadataPolicy::StripAll => write!(f, "Strip all"),
+        }
+    }
+}
+
+/// EXIF, XMP and ICC payloads of an image, as stored in the file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    /// A TIFF structure, without the JPEG "Exif\0\0" header.
+    pub exif: Option<Vec<u8>>,
+    /// An XMP packet.
+    pub xmp: Option<Vec<u8>>,
+    pub icc: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Reads the metadata of a JPEG, PNG or WebP file; TIFF files carry theirs in the image IFD, so only the ICC profile is read.
+    pub fn read(data: &[u8]) -> Self {
+        if data.starts_with(&[0xFF, 0xD8]) {
+            let mut icc_parts: Vec<(u8, &[u8])> = Vec::new();
+            let mut metadata = Metadata::default();
+            for (marker, payload) in jpeg_segments(data) {
+                match marker {
+                    0xE1 if payload.starts_with(b"Exif\0\0") => metadata.exif = metadata.exif.or(Some(payload[6..].to_vec())),
+                    0xE1 if payload.starts_with(JPEG_XMP_SIGNATURE) => {
+                        metadata.xmp = metadata.xmp.or(Some(payload[JPEG_XMP_SIGNATURE.len()..].to_vec()))
+                    }
+                    // Profiles over 64 KiB span several segments, each tagged with its sequence number
+                    0xE2 if payload.starts_with(JPEG_ICC_SIGNATURE) && payload.len() > JPEG_ICC_SIGNATURE.len() + 2 => {
+                        icc_parts.push((payload[JPEG_ICC_SIGNATURE.len()], &payload[JPEG_ICC_SIGNATURE.len() + 2..]));
+                    }
+                    _ => {}
+                }
+            }
+            icc_parts.sort_by_key(|&(sequence, _)| sequence);
+            if !icc_parts.is_empty() {
+                metadata.icc = Some(icc_parts.into_iter().flat_map(|(_, part)| part.iter().copied()).collect());
+            }
+            metadata
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            // iCCP is zlib-compressed, so let the decoder unpack it
+            let icc = image::codecs::png::PngDecoder::new(Cursor::new(data)).ok().and_then(|mut decoder| decoder.icc_profile());
+            let xmp = png_chunks(data).find_map(|(kind, payload)| (kind == b"iTXt").then(|| png_itxt_xmp(payload)).flatten());
+            Metadata { exif: read_exif(data).map(<[u8]>::to_vec), xmp: xmp.map(<[u8]>::to_vec), icc }
+        } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+            let chunk = |fourcc: &[u8]| riff_chunks(data).find_map(|(kind, payload)| (kind == fourcc).then(|| payload.to_vec()));
+            Metadata { exif: read_exif(data).map(<[u8]>::to_vec), xmp: chunk(b"XMP "), icc: chunk(b"ICCP") }
+        } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+            let icc = image::codecs::tiff::TiffDecoder::new(Cursor::new(data)).ok().and_then(|mut decoder| decoder.icc_profile());
+            Metadata { icc, ..Default::default() }
+        } else {
+            Metadata::default()
+        }
+    }
+
+    /// What `policy` lets through, and the sensitive fields it scrubbed on the way.
+    pub fn filtered(mut self, policy: MetadataPolicy) -> (Self, Vec<&'static str>) {
+        match policy {
+            MetadataPolicy::KeepAll => (self, Vec::new()),
+            MetadataPolicy::ScrubSensitive => {
+                let mut removed = self.exif.as_mut().map(|exif| scrub_exif(exif)).unwrap_or_default();
+                if let Some(xmp) = self.xmp.take() {
+                    // Packets that can't be edited safely are dropped whole
+                    match std::str::from_utf8(&xmp).ok().and_then(scrub_xmp) {
+                        Some((packet, xmp_removed)) => {
+                            self.xmp = Some(packet.into_bytes());
+                            removed.extend(xmp_removed);
+                        }
+                        None if SENSITIVE_XMP.iter().any(|(property, _)| String::from_utf8_lossy(&xmp).contains(property)) => {
+                            removed.push("XMP packet");
+                        }
+                        None => self.xmp = Some(xmp),
+                    }
+                }
+                (self, unique(removed))
+            }
+            MetadataPolicy::IccOnly => (Metadata { icc: self.icc, ..Default::default() }, Vec::new()),
+            MetadataPolicy::StripAll => (Metadata::default(), Vec::new()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.xmp.is_none() && self.icc.is_none()
+    }
+
+    /// Names of the present payloads, e.g. "EXIF, ICC".
+    pub fn summary(&self) -> String {
+        [("EXIF", &self.exif), ("XMP", &self.xmp), ("ICC", &self.icc)]
+            .iter()
+            .filter(|(_, payload)| payload.is_some())
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Removes the sensitive properties from an XMP packet, in both the attribute and the element form, returning
+/// the packet and what was found; everything else, such as rights and creator, is kept as written.
+/// `None` when an element isn't closed, so the packet can't be edited safely.
+fn scrub_xmp(xmp: &str) -> Option<(String, Vec<&'static str>)> {
+    let mut removed = Vec::new();
+    let mut kept = String::with_capacity(xmp.len());
+    let mut rest = xmp;
+    while let Some(start) = rest.find('<') {
+        kept.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let tag = &rest[..xml_tag_end(rest)];
+        let name = xml_tag_name(tag);
+        match name.and_then(sensitive_xmp_property) {
+            Some(field) => {
+                removed.push(field);
+                let end = if tag.ends_with("/>") {
+                    tag.len()
+                } else {
+                    let closing = format!("</{}>", name.unwrap_or_default());
+                    rest.find(&closing)? + closing.len()
+                };
+                // Drop the line break and indentation along with the element
+                kept.truncate(kept.trim_end().len());
+                rest = &rest[end..];
+            }
+            None if name.is_some() => {
+                kept.push_str(&scrub_xmp_attributes(tag, &mut removed));
+                rest = &rest[tag.len()..];
+            }
+            None => {
+                kept.push_str(tag);
+                rest = &rest[tag.len()..];
+            }
+        }
+    }
+    kept.push_str(rest);
+    Some((kept, removed))
+}
+
+/// Length of the markup at the start of `text` up to its closing `>`, skipping quoted values.
+fn xml_tag_end(text: &str) -> usize {
+    let mut quote = None;
+    for (index, c) in text.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if open == c => quote = None,
+            (None, '>') => return index + 1,
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Qualified name of a start tag, `None` for end tags, comments and processing instructions.
+fn xml_tag_name(tag: &str) -> Option<&str> {
+    let name = tag.strip_prefix('<').filter(|name| !name.starts_with(['/', '?', '!']))?;
+    let end = name.find(|c: char| c.is_whitespace() || c == '/' || c == '>').unwrap_or(name.len());
+    Some(&name[..end])
+}
+
+/// Reported name of a sensitive XMP property such as `exif:GPSLatitude`; namespace declarations never match.
+fn sensitive_xmp_property(qualified: &str) -> Option<&'static str> {
+    let (prefix, local) = qualified.split_once(':')?;
+    if prefix == "xmlns" {
+        return None;
+    }
+    SENSITIVE_XMP.iter().find(|(property, _)| local.contains(property)).map(|&(_, name)| name)
+}
+
+/// `tag` without its sensitive attributes, e.g. `exif:GPSLatitude="..."` on an `rdf:Description`.
+fn scrub_xmp_attributes(tag: &str, removed: &mut Vec<&'static str>) -> String {
+    let mut kept = String::with_capacity(tag.len());
+    let (mut copied, mut position) = (0, 0);
+    while let Some(equals) = tag[position..].find('=').map(|at| position + at) {
+        let name_end = tag[..equals].trim_end().len();
+        let name_start = tag[..name_end].rfind(char::is_whitespace).map_or(0, |at| at + 1);
+        let value = tag[equals + 1..].trim_start();
+        let value_start = tag.len() - value.len();
+        let Some(quote) = value.chars().next().filter(|&c| c == '"' || c == '\'') else { break };
+        let Some(value_end) = tag[value_start + 1..].find(quote).map(|at| value_start + at + 2) else { break };
+        if let Some(field) = sensitive_xmp_property(&tag[name_start..name_end]) {
+            removed.push(field);
+            kept.push_str(&tag[copied..tag[..name_start].trim_end().len()]);
+            copied = value_end;
+        }
+        position = value_end;
+    }
+    kept.push_str(&tag[copied..]);
+    kept
+}
+
+/// `names` without repeats, in order of first appearance.
+fn unique(mut names: Vec<&'static str>) -> Vec<&'static str> {
+    let mut seen = HashSet::new();
+    names.retain(|name| seen.insert(*name));
+    names
+}
+
+/// XMP packet of an uncompressed PNG iTXt chunk with the XMP keyword.
+fn png_itxt_xmp(payload: &[u8]) -> Option<&[u8]> {
+    let text = payload.strip_prefix(PNG_XMP_KEYWORD)?.strip_prefix(b"\0")?;
+    // Compression flag and method, then the language tag and translated keyword, both NUL-terminated
+    let (&compressed, rest) = text.split_first()?;
+    if compressed != 0 {
+        return None;
+    }
+    let rest = rest.get(1..)?;
+    let language_end = rest.iter().position(|&byte| byte == 0)?;
+    let rest = &rest[language_end + 1..];
+    let keyword_end = rest.iter().position(|&byte| byte == 0)?;
+    Some(&rest[keyword_end + 1..])
+}
+
+/// How a camera says its pixels must be turned to display upright (EXIF Orientation 1-8).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    Normal,
+    FlipHorizontal,
+    Rotate180,
+    FlipVertical,
+    /// Rotated 90° clockwise, then flipped horizontally.
+    Transpose,
+    /// Rotated 90° clockwise.
+    Rotate90,
+    /// Rotated 270° clockwise, then flipped horizontally.
+    Transverse,
+    /// Rotated 270° clockwise.
+    Rotate270,
+}
+
+impl Orientation {
+    pub fn from_exif(value: u16) -> Option<Self> {
+        match value {
+            1 => Some(Orientation::Normal),
+            2 => Some(Orientation::FlipHorizontal),
+            3 => Some(Orientation::Rotate180),
+            4 => Some(Orientation::FlipVertical),
+            5 => Some(Orientation::Transpose),
+            6 => Some(Orientation::Rotate90),
+            7 => Some(Orientation::Transverse),
+            8 => Some(Orientation::Rotate270),
+            _ => None,
+        }
+    }
+
+    /// Turns `img` upright.
+    pub fn apply(self, img: DynamicImage) -> DynamicImage {
+        match self {
+            Orientation::Normal => img,
+            Orientation::FlipHorizontal => img.fliph(),
+            Orientation::Rotate180 => img.rotate180(),
+            Orientation::FlipVertical => img.flipv(),
+            Orientation::Transpose => img.rotate90().fliph(),
+            Orientation::Rotate90 => img.rotate90(),
+            Orientation::Transverse => img.rotate270().fliph(),
+            Orientation::Rotate270 => img.rotate270(),
+        }
+    }
+}
+
+impl std::fmt::Display for Orientation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Orientation::Normal => write!(f, "upright"),
+            Orientation::FlipHorizontal => write!(f, "flipped horizontally"),
+            Orientation::Rotate180 => write!(f, "rotated 180°"),
+            Orientation::FlipVertical => write!(f, "flipped vertically"),
+            Orientation::Transpose => write!(f, "rotated 90° clockwise and flipped horizontally"),
+            Orientation::Rotate90 => write!(f, "rotated 90° clockwise"),
+            Orientation::Transverse => write!(f, "rotated 270° clockwise and flipped horizontally"),
+            Orientation::Rotate270 => write!(f, "rotated 90° counter-clockwise"),
+        }
+    }
+}
+
+/// Raw EXIF (a TIFF structure) embedded in a JPEG, PNG or WebP file, or a TIFF file itself.
+pub fn read_exif(data: &[u8]) -> Option<&[u8]> {
+    if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some(data)
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        jpeg_segments(data).find_map(|(marker, payload)| payload.strip_prefix(b"Exif\0\0").filter(|_| marker == 0xE1))
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_chunks(data).find_map(|(kind, payload)| (kind == b"eXIf").then_some(payload))
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        // Some writers keep the JPEG-style "Exif\0\0" header in the chunk
+        riff_chunks(data).find_map(|(kind, payload)| {
+            (kind == b"EXIF").then(|| payload.strip_prefix(b"Exif\0\0").unwrap_or(payload))
+        })
+    } else {
+        None
+    }
+}
+
+/// Orientation recorded in `exif`, if any.
+pub fn exif_orientation(exif: &[u8]) -> Option<Orientation> {
+    let tiff = Tiff::new(exif)?;
+    let ifd0 = tiff.u32(4)? as usize;
+    let value = tiff.entries(ifd0)?.find(|entry| entry.tag == ORIENTATION_TAG).and_then(|entry| tiff.u16(entry.offset + 8))?;
+    Orientation::from_exif(value)
+}
+
+/// Marks `exif` as upright (Orientation 1), once its pixels have been turned.
+pub fn reset_orientation(exif: &mut [u8]) {
+    let Some(tiff) = Tiff::new(exif) else { return };
+    let Some(ifd0) = tiff.u32(4) else { return };
+    let Some(entry) = tiff.entries(ifd0 as usize).and_then(|mut entries| entries.find(|entry| entry.tag == ORIENTATION_TAG)) else {
+        return;
+    };
+    let value = tiff.encode_u16(1);
+    exif[entry.offset + 8..entry.offset + 10].copy_from_slice(&value);
+}
+
+/// Removes GPS, serial numbers, owner names, maker notes and the thumbnail from `exif`, returning what was found.
+///
+/// Works in place: the dropped entries are compacted out of their IFDs and their values zeroed, so every
+/// offset the remaining fields use stays valid.
+pub fn scrub_exif(exif: &mut [u8]) -> Vec<&'static str> {
+    let mut edits = ScrubEdits::default();
+    if let Some(tiff) = Tiff::new(exif) {
+        if let Some(ifd0) = tiff.u32(4) {
+            scrub_ifd(&tiff, ifd0 as usize, 0, &mut edits);
+        }
+    }
+    for range in edits.zeroed {
+        exif[range].fill(0);
+    }
+    for (offset, bytes) in edits.writes {
+        exif[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+    unique(edits.removed)
+}
+
+/// Changes planned by `scrub_exif`, applied once the read-only pass is done.
+#[derive(Default)]
+struct ScrubEdits {
+    removed: Vec<&'static str>,
+    zeroed: Vec<Range<usize>>,
+    writes: Vec<(usize, Vec<u8>)>,
+}
+
+/// Plans the scrub of the IFD at `offset`; IFD0 (`depth` 0) also loses the thumbnail IFD chained after it.
+fn scrub_ifd(tiff: &Tiff, offset: usize, depth: usize, edits: &mut ScrubEdits) {
+    let Some(entries) = tiff.entries(offset) else { return };
+    let entries: Vec<IfdEntry> = entries.collect();
+    let mut kept = Vec::new();
+    for entry in &entries {
+        if entry.tag == GPS_IFD_TAG {
+            edits.removed.push("GPS");
+            if let Some(gps_ifd) = tiff.u32(entry.offset + 8) {
+                zero_ifd(tiff, gps_ifd as usize, edits);
+            }
+        } else if let Some(&(_, name)) = SENSITIVE_TAGS.iter().find(|(tag, _)| *tag == entry.tag) {
+            edits.removed.push(name);
+            edits.zeroed.extend(tiff.value_range(entry));
+        } else {
+            if entry.tag == EXIF_IFD_TAG && depth < MAX_IFD_DEPTH {
+                if let Some(exif_ifd) = tiff.u32(entry.offset + 8) {
+                    scrub_ifd(tiff, exif_ifd as usize, depth + 1, edits);
+                }
+            }
+            kept.push(entry);
+        }
+    }
+
+    let next_pointer = offset + 2 + entries.len() * 12;
+    let mut next = tiff.u32(next_pointer).unwrap_or(0);
+    if depth == 0 && next != 0 {
+        edits.removed.push("thumbnail");
+        zero_ifd(tiff, next as usize, edits);
+        next = 0;
+    }
+    if kept.len() == entries.len() && next == tiff.u32(next_pointer).unwrap_or(0) {
+        return;
+    }
+    let mut table = tiff.encode_u16(kept.len() as u16).to_vec();
+    for entry in kept {
+        table.extend_from_slice(&tiff.data[entry.offset..entry.offset + 12]);
+    }
+    table.extend_from_slice(&tiff.encode_u32(next));
+    table.resize(2 + entries.len() * 12 + 4, 0);
+    if tiff.data.get(offset..offset + table.len()).is_some() {
+        edits.writes.push((offset, table));
+    }
+}
+
+/// Plans zeroing the IFD at `offset`, its out-of-line values and any thumbnail it points to.
+fn zero_ifd(tiff: &Tiff, offset: usize, edits: &mut ScrubEdits) {
+    let Some(entries) = tiff.entries(offset) else { return };
+    let entries: Vec<IfdEntry> = entries.collect();
+    let value = |tag: u16| entries.iter().find(|entry| entry.tag == tag).and_then(|entry| tiff.u32(entry.offset + 8));
+    if let (Some(start), Some(length)) = (value(THUMBNAIL_OFFSET_TAG), value(THUMBNAIL_LENGTH_TAG)) {
+        let thumbnail = start as usize..start as usize + length as usize;
+        if tiff.data.get(thumbnail.clone()).is_some() {
+            edits.zeroed.push(thumbnail);
+        }
+    }
+    edits.zeroed.extend(entries.iter().filter_map(|entry| tiff.value_range(entry)));
+    edits.zeroed.push(offset..(offset + 2 + entries.len() * 12 + 4).min(tiff.data.len()));
+}
+
+/// `(marker, payload)` of each JPEG segment before the image data.
+fn jpeg_segments(data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut position = 2;
+    std::iter::from_fn(move || {
+        while data.get(position) == Some(&0xFF) && data.get(position + 1) == Some(&0xFF) {
+            position += 1; // Fill bytes
+        }
+        let (&0xFF, &marker) = (data.get(position)?, data.get(position + 1)?) else { return None };
+        // Start of scan or end of image: no more metadata segments
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let length = u16::from_be_bytes([*data.get(position + 2)?, *data.get(position + 3)?]) as usize;
+        let payload = data.get(position + 4..position + 2 + length.max(2))?;
+        position += 2 + length;
+        Some((marker, payload))
+    })
+}
+
+/// `(type, data)` of each PNG chunk.
+fn png_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut position = 8;
+    std::iter::from_fn(move || {
+        let length = u32::from_be_bytes(data.get(position..position + 4)?.try_into().ok()?) as usize;
+        let kind = data.get(position + 4..position + 8)?;
+        let payload = data.get(position + 8..position + 8 + length)?;
+        position += 12 + length;
+        Some((kind, payload))
+    })
+}
+
+/// `(FourCC, payload)` of each top-level chunk in a RIFF (WebP) file.
+pub(crate) fn riff_chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut position = 12;
+    std::iter::from_fn(move || {
+        let kind = data.get(position..position + 4)?;
+        let length = u32::from_le_bytes(data.get(position + 4..position + 8)?.try_into().ok()?) as usize;
+        let payload = data.get(position + 8..position + 8 + length)?;
+        position += 8 + length + (length & 1);
+        Some((kind, payload))
+    })
+}
+
+/// A TIFF header and IFD reader over borrowed bytes.
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+/// One 12-byte IFD entry; `offset` is where the entry starts in the TIFF data.
+struct IfdEntry {
+    tag: u16,
+    offset: usize,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(0..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Self { data, little_endian })
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    fn encode_u16(&self, value: u16) -> [u8; 2] {
+        if self.little_endian { value.to_le_bytes() } else { value.to_be_bytes() }
+    }
+
+    fn encode_u32(&self, value: u32) -> [u8; 4] {
+        if self.little_endian { value.to_le_bytes() } else { value.to_be_bytes() }
+    }
+
+    /// Where `entry`'s value is stored when it doesn't fit in the entry itself.
+    fn value_range(&self, entry: &IfdEntry) -> Option<Range<usize>> {
+        let size = match self.u16(entry.offset + 2)? {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 | 13 => 4,
+            5 | 10 | 12 => 8,
+            _ => return None,
+        };
+        let length = size * self.u32(entry.offset + 4)? as usize;
+        if length <= 4 {
+            return None;
+        }
+        let start = self.u32(entry.offset + 8)? as usize;
+        self.data.get(start..start + length).map(|_| start..start + length)
+    }
+
+    /// Entries of the IFD at `offset`, stopping at the first one past the end of the data.
+    fn entries(&self, offset: usize) -> Option<impl Iterator<Item = IfdEntry> + '_> {
+        let count = self.u16(offset)? as usize;
+        Some((0..count).map_while(move |index| {
+            let entry = offset + 2 + index * 12;
+            self.data.get(entry..entry + 12)?;
+            Some(IfdEntry { tag: self.u16(entry)?, offset: entry })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IFD0: u32 = 8;
+    const MAKE: u32 = IFD0 + 2 + 5 * 12 + 4;
+    const EXIF_IFD: u32 = MAKE + 10;
+    const CAPTURE_TIME: u32 = EXIF_IFD + 2 + 2 * 12 + 4;
+    const SERIAL: u32 = CAPTURE_TIME + 20;
+    const GPS_IFD: u32 = SERIAL + 8;
+    const LATITUDE: u32 = GPS_IFD + 2 + 2 * 12 + 4;
+    const IFD1: u32 = LATITUDE + 24;
+    const THUMBNAIL: u32 = IFD1 + 2 + 2 * 12 + 4;
+
+    /// Appends a little-endian IFD of `(tag, type, count, value or offset)` entries.
+    fn ifd(exif: &mut Vec<u8>, entries: &[(u16, u16, u32, u32)], next: u32) {
+        exif.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for &(tag, kind, count, value) in entries {
+            exif.extend_from_slice(&tag.to_le_bytes());
+            exif.extend_from_slice(&kind.to_le_bytes());
+            exif.extend_from_slice(&count.to_le_bytes());
+            exif.extend_from_slice(&value.to_le_bytes());
+        }
+        exif.extend_from_slice(&next.to_le_bytes());
+    }
+
+    /// Little-endian EXIF: IFD0 with Make, Orientation 6, the Exif and GPS pointers and a camera serial number;
+    /// an Exif IFD with the capture time and a body serial number; a GPS IFD; and a thumbnail IFD.
+    fn sample_exif() -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend_from_slice(&IFD0.to_le_bytes());
+        let camera_serial = u32::from_le_bytes(*b"A1\0\0");
+        ifd(&mut exif, &[(0x010F, 2, 10, MAKE), (0x0112, 3, 1, 6), (0x8769, 4, 1, EXIF_IFD), (0x8825, 4, 1, GPS_IFD), (0xC62F, 2, 3, camera_serial)], IFD1);
+        exif.extend_from_slice(b"CameraCo\0\0");
+        ifd(&mut exif, &[(0x9003, 2, 20, CAPTURE_TIME), (0xA431, 2, 8, SERIAL)], 0);
+        exif.extend_from_slice(b"2024:01:02 03:04:05\0");
+        exif.extend_from_slice(b"SN12345\0");
+        ifd(&mut exif, &[(0x0001, 2, 2, u32::from_le_bytes(*b"N\0\0\0")), (0x0002, 5, 3, LATITUDE)], 0);
+        for value in [52u32, 1, 31, 1, 7, 1] {
+            exif.extend_from_slice(&value.to_le_bytes());
+        }
+        ifd(&mut exif, &[(0x0201, 4, 1, THUMBNAIL), (0x0202, 4, 1, 4)], 0);
+        exif.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0xD9]);
+        assert_eq!(exif.len(), THUMBNAIL as usize + 4);
+        exif
+    }
+
+    fn tags(exif: &[u8], offset: u32) -> Vec<u16> {
+        let tiff = Tiff::new(exif).unwrap();
+        tiff.entries(offset as usize).unwrap().map(|entry| entry.tag).collect()
+    }
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|window| window == needle)
+    }
+
+    #[test]
+    fn scrubs_gps_serial_numbers_and_thumbnail() {
+        let mut exif = sample_exif();
+        // The two serial numbers are reported once, though GPS comes between them
+        assert_eq!(scrub_exif(&mut exif), vec!["serial number", "GPS", "thumbnail"]);
+
+        assert_eq!(tags(&exif, IFD0), vec![0x010F, 0x0112, 0x8769]);
+        assert_eq!(tags(&exif, EXIF_IFD), vec![0x9003]);
+        let tiff = Tiff::new(&exif).unwrap();
+        assert_eq!(tiff.u32(IFD0 as usize + 2 + 5 * 12), Some(0), "thumbnail IFD still linked");
+        assert!(!contains(&exif, b"SN12345"));
+        assert!(exif[LATITUDE as usize..IFD1 as usize].iter().all(|&byte| byte == 0));
+        assert!(exif[THUMBNAIL as usize..].iter().all(|&byte| byte == 0));
+        // Everything else keeps its place
+        assert_eq!(exif.len(), THUMBNAIL as usize + 4);
+        assert!(contains(&exif, b"CameraCo"));
+        assert!(contains(&exif, b"2024:01:02 03:04:05"));
+        assert_eq!(exif_orientation(&exif), Some(Orientation::Rotate90));
+    }
+
+    #[test]
+    fn resets_orientation() {
+        let mut exif = sample_exif();
+        assert_eq!(exif_orientation(&exif), Some(Orientation::Rotate90));
+        reset_orientation(&mut exif);
+        assert_eq!(exif_orientation(&exif), Some(Orientation::Normal));
+        assert_eq!(tags(&exif, IFD0).len(), 5);
+
+        // Big-endian, Orientation 3
+        let mut exif = b"MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x03\0\0\0\0\0\0".to_vec();
+        assert_eq!(exif_orientation(&exif), Some(Orientation::Rotate180));
+        reset_orientation(&mut exif);
+        assert_eq!(exif_orientation(&exif), Some(Orientation::Normal));
+    }
+
+    #[test]
+    fn truncated_exif_does_not_panic() {
+        let exif = sample_exif();
+        for length in 0..exif.len() {
+            let mut truncated = exif[..length].to_vec();
+            exif_orientation(&truncated);
+            reset_orientation(&mut truncated);
+            scrub_exif(&mut truncated);
+        }
+    }
+
+    #[test]
+    fn looping_ifds_do_not_hang() {
+        // IFD0 names itself as its Exif IFD, its GPS IFD and the next IFD
+        let mut exif = b"II*\0".to_vec();
+        exif.extend_from_slice(&IFD0.to_le_bytes());
+        ifd(&mut exif, &[(0x0112, 3, 1, 6), (0x8769, 4, 1, IFD0), (0x8825, 4, 1, IFD0)], IFD0);
+        assert_eq!(exif_orientation(&exif), Some(Orientation::Rotate90));
+        assert_eq!(scrub_exif(&mut exif.clone()), vec!["GPS", "thumbnail"]);
+    }
+
+    #[test]
+    fn scrubs_only_sensitive_xmp_properties() {
+        let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about="" xmlns:exif="http://ns.adobe.com/exif/1.0/" xmlns:aux="http://ns.adobe.com/exif/1.0/aux/"
+        xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+        exif:GPSLatitude="52,31.7N" xmp:CreateDate="2024-01-02T03:04:05" aux:SerialNumber='SN12345'>
+      <exif:GPSLongitude>13,24.1E</exif:GPSLongitude>
+      <aux:OwnerName/>
+      <dc:creator><rdf:Seq><rdf:li>Jane Doe</rdf:li></rdf:Seq></dc:creator>
+      <xmp:Thumbnails>
+        <rdf:Alt><rdf:li rdf:parseType="Resource"><xmpGImg:image>/9j/4AAQ</xmpGImg:image></rdf:li></rdf:Alt>
+      </xmp:Thumbnails>
+      <dc:rights><rdf:Alt><rdf:li xml:lang="x-default">(c) Jane Doe</rdf:li></rdf:Alt></dc:rights>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+        let metadata = Metadata { exif: Some(sample_exif()), xmp: Some(xmp.as_bytes().to_vec()), icc: None };
+        let (filtered, removed) = metadata.filtered(MetadataPolicy::ScrubSensitive);
+        assert_eq!(removed, vec!["serial number", "GPS", "thumbnail", "owner name"]);
+
+        let packet = String::from_utf8(filtered.xmp.unwrap()).unwrap();
+        for gone in ["GPS", "SerialNumber", "SN12345", "OwnerName", "Thumbnails", "/9j/4AAQ"] {
+            assert!(!packet.contains(gone), "{} left in {}", gone, packet);
+        }
+        for kept in ["xmp:CreateDate=\"2024-01-02T03:04:05\"", "<rdf:li>Jane Doe</rdf:li>", "(c) Jane Doe</rdf:li></rdf:Alt></dc:rights>", "</x:xmpmeta>"] {
+            assert!(packet.contains(kept), "{} missing from {}", kept, packet);
+        }
+        assert!(packet.contains("xmlns:exif="), "namespace declarations are kept");
+    }
+
+    #[test]
+    fn drops_xmp_packets_it_cannot_edit() {
+        let unclosed = b"<rdf:Description><exif:GPSLatitude>52,31.7N</rdf:Description>".to_vec();
+        let (filtered, removed) = Metadata { xmp: Some(unclosed), ..Default::default() }.filtered(MetadataPolicy::ScrubSensitive);
+        assert_eq!((filtered.xmp, removed), (None, vec!["XMP packet"]));
+
+        let clean = b"<rdf:Description dc:format=\"image/jpeg\"/>".to_vec();
+        let (filtered, removed) = Metadata { xmp: Some(clean.clone()), ..Default::default() }.filtered(MetadataPolicy::ScrubSensitive);
+        assert_eq!((filtered.xmp, removed), (Some(clean), Vec::new()));
+    }
+}
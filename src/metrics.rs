@@ -0,0 +1,257 @@
+// metrics.rs
+use image::DynamicImage;
+
+/// Full-reference fidelity metric comparing an encoded image against its source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QualityMetric {
+    Ssim,
+    Psnr,
+}
+
+impl QualityMetric {
+    pub const ALL: [QualityMetric; 2] = [QualityMetric::Ssim, QualityMetric::Psnr];
+
+    /// A reasonable "visually transparent" target for the metric.
+    pub fn default_target(self) -> f64 {
+        match self {
+            QualityMetric::Ssim => 0.95,
+            QualityMetric::Psnr => 40.0,
+        }
+    }
+
+    pub fn measure(self, reference: &DynamicImage, distorted: &DynamicImage) -> f64 {
+        match self {
+            QualityMetric::Ssim => ssim(reference, distorted),
+            QualityMetric::Psnr => psnr(reference, distorted),
+        }
+    }
+
+    pub fn format_value(self, value: f64) -> String {
+        match self {
+            QualityMetric::Ssim => format!("{:.4}", value),
+            QualityMetric::Psnr if value.is_infinite() => "inf dB".to_string(),
+            QualityMetric::Psnr => format!("{:.2} dB", value),
+        }
+    }
+}
+
+impl std::fmt::Display for QualityMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QualityMetric::Ssim => write!(f, "SSIM"),
+            QualityMetric::Psnr => write!(f, "PSNR"),
+        }
+    }
+}
+
+/// Fidelity of an encoded image against its (resized) source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FidelityMetrics {
+    pub psnr: f64,
+    pub ssim: f64,
+    pub ms_ssim: f64,
+}
+
+impl FidelityMetrics {
+    pub fn measure(reference: &DynamicImage, distorted: &DynamicImage) -> Self {
+        Self {
+            psnr: psnr(reference, distorted),
+            ssim: ssim(reference, distorted),
+            ms_ssim: ms_ssim(reference, distorted),
+        }
+    }
+}
+
+/// RGB samples premultiplied by alpha, so hidden pixels under full transparency don't count.
+fn premultiplied_rgb(img: &DynamicImage) -> Vec<[f64; 3]> {
+    img.to_rgba8()
+        .pixels()
+        .map(|p| {
+            let alpha = p[3] as f64 / 255.0;
+            [p[0] as f64 * alpha, p[1] as f64 * alpha, p[2] as f64 * alpha]
+        })
+        .collect()
+}
+
+fn luma_plane(img: &DynamicImage) -> Vec<f64> {
+    premultiplied_rgb(img)
+        .iter()
+        .map(|[r, g, b]| 0.299 * r + 0.587 * g + 0.114 * b)
+        .collect()
+}
+
+/// Peak signal-to-noise ratio over the RGB channels, in dB. Identical images give infinity.
+pub fn psnr(reference: &DynamicImage, distorted: &DynamicImage) -> f64 {
+    if reference.width() != distorted.width() || reference.height() != distorted.height() {
+        return 0.0;
+    }
+    let a = premultiplied_rgb(reference);
+    let b = premultiplied_rgb(distorted);
+    if a.is_empty() {
+        return f64::INFINITY;
+    }
+
+    let squared_error: f64 = a.iter().zip(&b)
+        .map(|(pa, pb)| (0..3).map(|c| (pa[c] - pb[c]).powi(2)).sum::<f64>())
+        .sum();
+    let mse = squared_error / (a.len() * 3) as f64;
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+const SSIM_WINDOW: usize = 8;
+const SSIM_STRIDE: usize = 4;
+
+/// Mean structural similarity of the luma planes, from 8x8 windows every 4 pixels.
+pub fn ssim(reference: &DynamicImage, distorted: &DynamicImage) -> f64 {
+    if reference.width() != distorted.width() || reference.height() != distorted.height() {
+        return 0.0;
+    }
+    let (width, height) = (reference.width() as usize, reference.height() as usize);
+    ssim_plane(&luma_plane(reference), &luma_plane(distorted), width, height).0
+}
+
+/// Per-scale weights from Wang et al., "Multi-scale structural similarity for image quality assessment".
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// Multi-scale SSIM of the luma planes over up to five dyadic scales.
+pub fn ms_ssim(reference: &DynamicImage, distorted: &DynamicImage) -> f64 {
+    if reference.width() != distorted.width() || reference.height() != distorted.height() {
+        return 0.0;
+    }
+    let (mut width, mut height) = (reference.width() as usize, reference.height() as usize);
+    let mut a = luma_plane(reference);
+    let mut b = luma_plane(distorted);
+
+    // Small images get fewer scales, with the weights renormalized
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len() && (width >> scales) >= SSIM_WINDOW && (height >> scales) >= SSIM_WINDOW {
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let weight_sum: f64 = weights.iter().sum();
+
+    let mut result = 1.0;
+    for (scale, weight) in weights.iter().enumerate() {
+        let (ssim, contrast_structure) = ssim_plane(&a, &b, width, height);
+        if scale + 1 == scales {
+            result *= ssim.max(0.0).powf(weight / weight_sum);
+        } else {
+            result *= contrast_structure.max(0.0).powf(weight / weight_sum);
+            a = downsample(&a, width, height);
+            b = downsample(&b, width, height);
+            width /= 2;
+            height /= 2;
+        }
+    }
+    result
+}
+
+/// Halves a plane in both dimensions by averaging 2x2 blocks.
+fn downsample(plane: &[f64], width: usize, height: usize) -> Vec<f64> {
+    let (half_w, half_h) = (width / 2, height / 2);
+    let mut out = Vec::with_capacity(half_w * half_h);
+    for y in 0..half_h {
+        for x in 0..half_w {
+            let top = (2 * y) * width + 2 * x;
+            let bottom = top + width;
+            out.push((plane[top] + plane[top + 1] + plane[bottom] + plane[bottom + 1]) / 4.0);
+        }
+    }
+    out
+}
+
+/// Returns the mean SSIM and the mean contrast-structure term over all windows.
+fn ssim_plane(a: &[f64], b: &[f64], width: usize, height: usize) -> (f64, f64) {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    if width == 0 || height == 0 {
+        return (1.0, 1.0);
+    }
+    // Images smaller than a window are compared as a single window
+    let window_w = SSIM_WINDOW.min(width);
+    let window_h = SSIM_WINDOW.min(height);
+
+    let (mut total_ssim, mut total_cs) = (0.0, 0.0);
+    let mut windows = 0usize;
+    let mut y = 0;
+    while y + window_h <= height {
+        let mut x = 0;
+        while x + window_w <= width {
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for row in y..y + window_h {
+                for col in x..x + window_w {
+                    let (va, vb) = (a[row * width + col], b[row * width + col]);
+                    sum_a += va;
+                    sum_b += vb;
+                    sum_aa += va * va;
+                    sum_bb += vb * vb;
+                    sum_ab += va * vb;
+                }
+            }
+            let n = (window_w * window_h) as f64;
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+
+            let luminance = (2.0 * mean_a * mean_b + C1) / (mean_a * mean_a + mean_b * mean_b + C1);
+            let contrast_structure = (2.0 * covariance + C2) / (var_a + var_b + C2);
+            total_ssim += luminance * contrast_structure;
+            total_cs += contrast_structure;
+            windows += 1;
+            x += SSIM_STRIDE;
+        }
+        y += SSIM_STRIDE;
+    }
+    (total_ssim / windows as f64, total_cs / windows as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// A 64x48 diagonal gradient with some texture, brightened by `offset`.
+    fn gradient(offset: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 48, |x, y| {
+            let texture = ((x * 7 + y * 13) % 17) as u8;
+            Rgb([(x * 2) as u8 + texture + offset, (y * 3) as u8 + offset, 100 + texture + offset])
+        }))
+    }
+
+    #[test]
+    fn identical_images_score_perfectly() {
+        let img = gradient(0);
+        assert_eq!(psnr(&img, &img), f64::INFINITY);
+        assert!((ssim(&img, &img) - 1.0).abs() < 1e-9);
+        assert!((ms_ssim(&img, &img) - 1.0).abs() < 1e-9);
+        assert_eq!(QualityMetric::Psnr.format_value(psnr(&img, &img)), "inf dB");
+    }
+
+    #[test]
+    fn scores_a_known_distortion() {
+        // Every sample off by 10: MSE 100, so PSNR is 10 * log10(255^2 / 100)
+        let (reference, brighter) = (gradient(0), gradient(10));
+        assert!((psnr(&reference, &brighter) - 28.1308).abs() < 1e-3);
+
+        // A uniform shift keeps the structure, so SSIM drops only a little; a heavier one drops it further
+        let slight = ssim(&reference, &brighter);
+        let heavy = ssim(&reference, &gradient(60));
+        assert!(slight < 1.0 && slight > 0.9, "{}", slight);
+        assert!(heavy < slight, "{} vs {}", heavy, slight);
+        assert!(ms_ssim(&reference, &gradient(60)) < 1.0);
+    }
+
+    #[test]
+    fn mismatched_sizes_score_zero() {
+        let small = DynamicImage::ImageRgb8(RgbImage::new(32, 48));
+        assert_eq!(psnr(&gradient(0), &small), 0.0);
+        assert_eq!(ssim(&gradient(0), &small), 0.0);
+        assert_eq!(ms_ssim(&gradient(0), &small), 0.0);
+    }
+}
@@ -0,0 +1,177 @@
+// pixel_format.rs
+use crate::conversion::ConversionOptions;
+use image::{ColorType, DynamicImage, ImageBuffer, Pixel, Rgb, RgbImage, Rgba};
+
+/// 4x4 Bayer matrix; thresholds are `(value + 0.5) / 16`.
+const BAYER_4X4: [[f32; 4]; 4] = [[0.0, 8.0, 2.0, 10.0], [12.0, 4.0, 14.0, 6.0], [3.0, 11.0, 1.0, 9.0], [15.0, 7.0, 13.0, 5.0]];
+
+/// What happens to transparency before encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaHandling {
+    Keep,
+    /// Composite onto `ConversionOptions::background`, dropping the alpha channel.
+    Flatten,
+}
+
+impl AlphaHandling {
+    pub const ALL: [AlphaHandling; 2] = [AlphaHandling::Keep, AlphaHandling::Flatten];
+}
+
+impl std::fmt::Display for AlphaHandling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlphaHandling::Keep => write!(f, "Keep"),
+            AlphaHandling::Flatten => write!(f, "Flatten onto background"),
+        }
+    }
+}
+
+/// Brings `img` to the 8-bit RGB or RGBA layout every encoder accepts, returning the steps taken.
+///
+/// Deeper images are dithered rather than truncated, so smooth gradients don't band; gray images are expanded.
+/// `source` is the decoded layout, which the steps describe even if resizing already changed it.
+pub fn to_encodable(img: DynamicImage, source: ColorType, options: &ConversionOptions) -> (DynamicImage, Vec<String>) {
+    let mut steps = Vec::new();
+    let flatten = options.alpha == AlphaHandling::Flatten && img.color().has_alpha();
+    if is_deep(source) {
+        steps.push(format!("{:?} dithered to 8-bit", source));
+    }
+    if matches!(source, ColorType::L8 | ColorType::La8 | ColorType::L16 | ColorType::La16) {
+        let expanded = if source.has_alpha() && !flatten { "Gray + alpha expanded to RGBA" } else { "Gray expanded to RGB" };
+        steps.push(expanded.to_string());
+    }
+
+    let deep = is_deep(img.color());
+    let img = if flatten {
+        DynamicImage::ImageRgb8(flatten_dithered(&img.into_rgba32f(), options.background))
+    } else if deep && img.color().has_alpha() {
+        DynamicImage::ImageRgba8(dither(&img.into_rgba32f()))
+    } else if deep {
+        DynamicImage::ImageRgb8(dither(&img.into_rgb32f()))
+    } else {
+        match img {
+            DynamicImage::ImageLuma8(_) => DynamicImage::ImageRgb8(img.into_rgb8()),
+            DynamicImage::ImageLumaA8(_) => DynamicImage::ImageRgba8(img.into_rgba8()),
+            other => other,
+        }
+    };
+    if flatten {
+        let [r, g, b] = options.background;
+        steps.push(format!("Alpha flattened onto #{:02x}{:02x}{:02x}", r, g, b));
+    }
+    (img, steps)
+}
+
+/// More than 8 bits per channel.
+pub(crate) fn is_deep(color: ColorType) -> bool {
+    color.bytes_per_pixel() / color.channel_count() > 1
+}
+
+/// Quantizes 0-1 float samples to 8 bits with ordered dithering; alpha is rounded instead, as noise there shows as fringes.
+fn dither<P>(buffer: &ImageBuffer<P, Vec<f32>>) -> ImageBuffer<P::Quantized, Vec<u8>>
+where
+    P: Pixel<Subpixel = f32> + Quantize,
+{
+    ImageBuffer::from_fn(buffer.width(), buffer.height(), |x, y| {
+        let threshold = (BAYER_4X4[(y % 4) as usize][(x % 4) as usize] + 0.5) / 16.0;
+        P::quantize(buffer.get_pixel(x, y), threshold)
+    })
+}
+
+/// Composites straight-alpha float samples onto `background`, dithering the result to 8 bits.
+fn flatten_dithered(buffer: &ImageBuffer<Rgba<f32>, Vec<f32>>, background: [u8; 3]) -> RgbImage {
+    let background = background.map(|channel| channel as f32 / 255.0);
+    ImageBuffer::from_fn(buffer.width(), buffer.height(), |x, y| {
+        let [r, g, b, a] = buffer.get_pixel(x, y).0;
+        let alpha = a.clamp(0.0, 1.0);
+        let threshold = (BAYER_4X4[(y % 4) as usize][(x % 4) as usize] + 0.5) / 16.0;
+        let blend = |channel: f32, background: f32| quantize(channel * alpha + background * (1.0 - alpha), threshold);
+        Rgb([blend(r, background[0]), blend(g, background[1]), blend(b, background[2])])
+    })
+}
+
+fn quantize(value: f32, threshold: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0 + threshold).floor().min(255.0) as u8
+}
+
+/// Float pixel layouts `dither` can quantize.
+trait Quantize: Pixel<Subpixel = f32> {
+    type Quantized: Pixel<Subpixel = u8>;
+
+    fn quantize(&self, threshold: f32) -> Self::Quantized;
+}
+
+impl Quantize for Rgb<f32> {
+    type Quantized = Rgb<u8>;
+
+    fn quantize(&self, threshold: f32) -> Rgb<u8> {
+        Rgb(self.0.map(|channel| quantize(channel, threshold)))
+    }
+}
+
+impl Quantize for Rgba<f32> {
+    type Quantized = Rgba<u8>;
+
+    fn quantize(&self, threshold: f32) -> Rgba<u8> {
+        let [r, g, b, a] = self.0;
+        Rgba([quantize(r, threshold), quantize(g, threshold), quantize(b, threshold), quantize(a, 0.5)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayAlphaImage, LumaA, RgbaImage};
+
+    fn convert(img: DynamicImage, options: &ConversionOptions) -> (DynamicImage, Vec<String>) {
+        let source = img.color();
+        to_encodable(img, source, options)
+    }
+
+    #[test]
+    fn brings_every_layout_to_8_bit_rgb_or_rgba() {
+        let options = ConversionOptions::default();
+        let rgba16 = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(4, 4, Rgba([0, 0, 0, 65535])));
+        let gray16 = DynamicImage::ImageLuma16(ImageBuffer::from_pixel(4, 4, image::Luma([0])));
+        let gray_alpha = DynamicImage::ImageLumaA8(GrayAlphaImage::from_pixel(4, 4, LumaA([90, 128])));
+        let rgba = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([1, 2, 3, 4])));
+        let cases: [(DynamicImage, ColorType, &[&str]); 4] = [
+            (rgba16, ColorType::Rgba8, &["Rgba16 dithered to 8-bit"]),
+            (gray16, ColorType::Rgb8, &["L16 dithered to 8-bit", "Gray expanded to RGB"]),
+            (gray_alpha, ColorType::Rgba8, &["Gray + alpha expanded to RGBA"]),
+            (rgba.clone(), ColorType::Rgba8, &[]),
+        ];
+        for (img, color, steps) in cases {
+            let (encodable, taken) = convert(img, &options);
+            assert_eq!(encodable.color(), color);
+            assert_eq!(taken, steps);
+        }
+        assert_eq!(convert(rgba.clone(), &options).0, rgba);
+    }
+
+    #[test]
+    fn dithers_between_the_nearest_8_bit_levels() {
+        // 33024 / 257 = 128.498: halfway between 128 and 129, so half of each 4x4 tile rounds up
+        let img = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(4, 4, Rgb([33024, 33024, 33024])));
+        let dithered = dither(&img.into_rgb32f());
+        let levels: Vec<u8> = dithered.pixels().map(|pixel| pixel.0[0]).collect();
+        assert_eq!(levels.iter().filter(|&&level| level == 128).count(), 8);
+        assert_eq!(levels.iter().filter(|&&level| level == 129).count(), 8);
+    }
+
+    #[test]
+    fn flattens_onto_the_background() {
+        let options = ConversionOptions::new().flatten_onto([255, 255, 255]);
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 128])));
+        let (flattened, steps) = convert(img, &options);
+        assert_eq!(flattened.color(), ColorType::Rgb8);
+        assert_eq!(steps, ["Alpha flattened onto #ffffff"]);
+        // Half-opaque red over white: 0 * 128/255 + 255 * 127/255 = 127 in green and blue
+        assert!(flattened.into_rgb8().pixels().all(|pixel| pixel.0 == [255, 127, 127]));
+
+        let (flattened, steps) = convert(DynamicImage::ImageLumaA8(GrayAlphaImage::from_pixel(2, 2, LumaA([0, 0]))), &options);
+        assert_eq!(flattened.color(), ColorType::Rgb8);
+        assert_eq!(steps, ["Gray expanded to RGB", "Alpha flattened onto #ffffff"]);
+        assert_eq!(flattened.into_rgb8().get_pixel(0, 0).0, [255, 255, 255]);
+    }
+}
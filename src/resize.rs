@@ -0,0 +1,347 @@
+// resize.rs
+mod smart_crop;
+
+use crate::conversion::ConversionOptions;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, Rgba32FImage};
+
+/// How `ConversionOptions::width`/`height` (or `max_edge`/`scale_percent`) map the source to the output size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Stretch to exactly `width` x `height`, ignoring the aspect ratio.
+    Exact,
+    /// Largest size that fits within `width` x `height`.
+    Fit,
+    /// Cover `width` x `height` and center-crop the overflow.
+    Fill,
+    /// Like `Fill`, but crop to the window with the most detail, color and skin tones.
+    SmartCrop,
+    /// Scale so the longest edge is `max_edge`.
+    MaxEdge,
+    /// Scale both sides by `scale_percent`.
+    Percentage,
+    /// Scale to `width`, height follows the aspect ratio.
+    Width,
+    /// Scale to `height`, width follows the aspect ratio.
+    Height,
+}
+
+impl ResizeMode {
+    pub const ALL: [ResizeMode; 8] = [
+        ResizeMode::Exact,
+        ResizeMode::Fit,
+        ResizeMode::Fill,
+        ResizeMode::SmartCrop,
+        ResizeMode::MaxEdge,
+        ResizeMode::Percentage,
+        ResizeMode::Width,
+        ResizeMode::Height,
+    ];
+
+    /// Whether the mode crops the source to the box's aspect ratio.
+    pub fn crops(self) -> bool {
+        matches!(self, ResizeMode::Fill | ResizeMode::SmartCrop)
+    }
+}
+
+impl std::fmt::Display for ResizeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResizeMode::Exact => write!(f, "Stretch"),
+            ResizeMode::Fit => write!(f, "Fit within"),
+            ResizeMode::Fill => write!(f, "Fill and crop"),
+            ResizeMode::SmartCrop => write!(f, "Smart crop"),
+            ResizeMode::MaxEdge => write!(f, "Longest edge"),
+            ResizeMode::Percentage => write!(f, "Percentage"),
+            ResizeMode::Width => write!(f, "Width only"),
+            ResizeMode::Height => write!(f, "Height only"),
+        }
+    }
+}
+
+/// Resampling kernel used for every resize.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeFilter {
+    /// Blocky but exact, for pixel art.
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub const ALL: [ResizeFilter; 5] = [
+        ResizeFilter::Nearest,
+        ResizeFilter::Triangle,
+        ResizeFilter::CatmullRom,
+        ResizeFilter::Gaussian,
+        ResizeFilter::Lanczos3,
+    ];
+
+    pub fn filter_type(self) -> FilterType {
+        match self {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl std::fmt::Display for ResizeFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResizeFilter::Nearest => write!(f, "Nearest"),
+            ResizeFilter::Triangle => write!(f, "Triangle"),
+            ResizeFilter::CatmullRom => write!(f, "Catmull-Rom"),
+            ResizeFilter::Gaussian => write!(f, "Gaussian"),
+            ResizeFilter::Lanczos3 => write!(f, "Lanczos3"),
+        }
+    }
+}
+
+/// A window of the source image, in source pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::fmt::Display for CropRect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// Result of `resize`.
+pub struct Resized {
+    pub image: DynamicImage,
+    /// Window kept by `ResizeMode::Fill` and `ResizeMode::SmartCrop`.
+    pub crop: Option<CropRect>,
+    /// `Some` when the pixels were resampled, holding whether that ran in linear light.
+    pub linear_light: Option<bool>,
+}
+
+/// Largest window of `source` with the aspect ratio of `target`.
+fn crop_window(source: (u32, u32), target: (u32, u32)) -> (u32, u32) {
+    let (source_w, source_h) = (source.0.max(1) as f64, source.1.max(1) as f64);
+    let (target_w, target_h) = (target.0.max(1) as f64, target.1.max(1) as f64);
+    if source_w * target_h > source_h * target_w {
+        (((source_h * target_w / target_h).round() as u32).clamp(1, source.0.max(1)), source.1.max(1))
+    } else {
+        (source.0.max(1), ((source_w * target_h / target_w).round() as u32).clamp(1, source.1.max(1)))
+    }
+}
+
+/// Output size of a `source` image resized per `options`.
+pub fn scaled_dimensions(source: (u32, u32), options: &ConversionOptions) -> (u32, u32) {
+    let (source_w, source_h) = (source.0.max(1) as f64, source.1.max(1) as f64);
+    let (box_w, box_h) = (options.width.max(1) as f64, options.height.max(1) as f64);
+    let (mut scale_x, mut scale_y) = match options.resize_mode {
+        ResizeMode::Exact => (box_w / source_w, box_h / source_h),
+        ResizeMode::Fit => {
+            let scale = (box_w / source_w).min(box_h / source_h);
+            (scale, scale)
+        }
+        ResizeMode::Fill | ResizeMode::SmartCrop => {
+            // The cropped window already has the box's aspect ratio; smaller than the box when upscaling is off
+            let window = crop_window(source, (options.width, options.height));
+            if options.never_upscale && window.0 < options.width.max(1) {
+                return window;
+            }
+            return (options.width.max(1), options.height.max(1));
+        }
+        ResizeMode::MaxEdge => {
+            let scale = options.max_edge.max(1) as f64 / source_w.max(source_h);
+            (scale, scale)
+        }
+        ResizeMode::Percentage => {
+            let scale = options.scale_percent.max(0.0) as f64 / 100.0;
+            (scale, scale)
+        }
+        ResizeMode::Width => (box_w / source_w, box_w / source_w),
+        ResizeMode::Height => (box_h / source_h, box_h / source_h),
+    };
+    if options.never_upscale {
+        scale_x = scale_x.min(1.0);
+        scale_y = scale_y.min(1.0);
+    }
+    (
+        ((source_w * scale_x).round() as u32).max(1),
+        ((source_h * scale_y).round() as u32).max(1),
+    )
+}
+
+/// Size to rasterize a vector source of `intrinsic` size at: the output size, or for the cropping modes the
+/// smallest size covering the box, which leaves `resize` only the crop. Vectors scale without loss, so
+/// `never_upscale` doesn't apply.
+pub fn raster_size(intrinsic: (u32, u32), options: &ConversionOptions) -> (u32, u32) {
+    if options.resize_mode.crops() {
+        let (source_w, source_h) = (intrinsic.0.max(1) as f64, intrinsic.1.max(1) as f64);
+        let (box_w, box_h) = (options.width.max(1), options.height.max(1));
+        let scale = (box_w as f64 / source_w).max(box_h as f64 / source_h);
+        return (((source_w * scale).round() as u32).max(box_w), ((source_h * scale).round() as u32).max(box_h));
+    }
+    let options = ConversionOptions { never_upscale: false, ..options.clone() };
+    scaled_dimensions(intrinsic, &options)
+}
+
+/// Resizes `img` per `options`, cropping first in the `Fill` modes; untouched when the size wouldn't change.
+pub fn resize(img: DynamicImage, options: &ConversionOptions) -> Resized {
+    let (img, crop) = match options.resize_mode {
+        ResizeMode::Fill | ResizeMode::SmartCrop => {
+            let (window_w, window_h) = crop_window(img.dimensions(), (options.width, options.height));
+            let crop = if options.resize_mode == ResizeMode::SmartCrop {
+                smart_crop::best_window(&img, window_w, window_h)
+            } else {
+                CropRect { x: (img.width() - window_w) / 2, y: (img.height() - window_h) / 2, width: window_w, height: window_h }
+            };
+            let img = if (crop.width, crop.height) == img.dimensions() {
+                img
+            } else {
+                img.crop_imm(crop.x, crop.y, crop.width, crop.height)
+            };
+            (img, Some(crop))
+        }
+        _ => (img, None),
+    };
+
+    let (width, height) = scaled_dimensions(img.dimensions(), options);
+    if (width, height) == img.dimensions() {
+        return Resized { image: img, crop, linear_light: None };
+    }
+    let linear_light = uses_linear_light(options, img.dimensions(), (width, height));
+    Resized { image: resize_exact(&img, width, height, options), crop, linear_light: Some(linear_light) }
+}
+
+/// Whether a `from` -> `to` resize runs in linear light under `options`.
+pub fn uses_linear_light(options: &ConversionOptions, from: (u32, u32), to: (u32, u32)) -> bool {
+    // Nearest copies pixels, so there is nothing to blend
+    options.linear_light && options.resize_filter != ResizeFilter::Nearest && (to.0 < from.0 || to.1 < from.1)
+}
+
+/// Whether a resize of `img` blends premultiplied samples under `options`.
+pub fn uses_premultiplied_alpha(options: &ConversionOptions, img: &DynamicImage) -> bool {
+    options.premultiplied_alpha && options.resize_filter != ResizeFilter::Nearest && img.color().has_alpha()
+}
+
+/// Resamples `img` to exactly `width` x `height` with the configured filter.
+pub fn resize_exact(img: &DynamicImage, width: u32, height: u32, options: &ConversionOptions) -> DynamicImage {
+    let filter = options.resize_filter.filter_type();
+    let linear = uses_linear_light(options, img.dimensions(), (width, height));
+    if !linear && !uses_premultiplied_alpha(options, img) {
+        return img.resize_exact(width, height, filter);
+    }
+
+    // Averaging gamma-encoded values darkens fine detail, and straight alpha lets the color of transparent
+    // pixels bleed into edges as dark halos; blend premultiplied (and, if asked, linear) samples instead
+    let decode = |value: f32| if linear { srgb_to_linear(value) } else { value };
+    let rgba = img.to_rgba32f();
+    let premultiplied: Rgba32FImage = ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        Rgba([decode(r) * a, decode(g) * a, decode(b) * a, a])
+    });
+    let resized = image::imageops::resize(&premultiplied, width, height, filter);
+    let unpremultiplied: Rgba32FImage = ImageBuffer::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = resized.get_pixel(x, y).0;
+        let alpha = a.clamp(0.0, 1.0);
+        if alpha <= 0.0 {
+            return Rgba([0.0, 0.0, 0.0, 0.0]);
+        }
+        let encode = |value: f32| {
+            let value = (value / alpha).clamp(0.0, 1.0);
+            if linear { linear_to_srgb(value) } else { value }
+        };
+        Rgba([encode(r), encode(g), encode(b), alpha])
+    });
+
+    // Deep sources stay in float, to be dithered rather than truncated before encoding
+    if crate::pixel_format::is_deep(img.color()) {
+        DynamicImage::ImageRgba32F(unpremultiplied)
+    } else {
+        DynamicImage::ImageRgba8(DynamicImage::ImageRgba32F(unpremultiplied).into_rgba8())
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn boxed(mode: ResizeMode, width: u32, height: u32) -> ConversionOptions {
+        ConversionOptions::new().resize(width, height).resize_mode(mode)
+    }
+
+    #[test]
+    fn scales_to_each_mode() {
+        let source = (400, 200);
+        assert_eq!(scaled_dimensions(source, &boxed(ResizeMode::Exact, 100, 100)), (100, 100));
+        assert_eq!(scaled_dimensions(source, &boxed(ResizeMode::Fit, 100, 100)), (100, 50));
+        assert_eq!(scaled_dimensions(source, &boxed(ResizeMode::Fit, 300, 50)), (100, 50));
+        assert_eq!(scaled_dimensions(source, &boxed(ResizeMode::Fill, 100, 100)), (100, 100));
+        assert_eq!(scaled_dimensions(source, &boxed(ResizeMode::SmartCrop, 90, 30)), (90, 30));
+        assert_eq!(scaled_dimensions(source, &boxed(ResizeMode::Width, 100, 0)), (100, 50));
+        assert_eq!(scaled_dimensions(source, &boxed(ResizeMode::Height, 0, 100)), (200, 100));
+        assert_eq!(scaled_dimensions(source, &ConversionOptions::new().max_edge(100)), (100, 50));
+        assert_eq!(scaled_dimensions((200, 400), &ConversionOptions::new().max_edge(100)), (50, 100));
+        assert_eq!(scaled_dimensions(source, &ConversionOptions::new().scale(25.0)), (100, 50));
+        // Nothing collapses below one pixel
+        assert_eq!(scaled_dimensions(source, &ConversionOptions::new().scale(0.1)), (1, 1));
+    }
+
+    #[test]
+    fn never_upscale_keeps_small_sources() {
+        let source = (400, 200);
+        let fit = boxed(ResizeMode::Fit, 800, 800);
+        assert_eq!(scaled_dimensions(source, &fit), (800, 400));
+        assert_eq!(scaled_dimensions(source, &fit.never_upscale(true)), (400, 200));
+        assert_eq!(scaled_dimensions(source, &ConversionOptions::new().scale(200.0).never_upscale(true)), (400, 200));
+        assert_eq!(scaled_dimensions(source, &boxed(ResizeMode::Height, 0, 100).never_upscale(true)), (200, 100));
+        // The cropping modes keep the largest window with the box's aspect ratio instead
+        let fill = boxed(ResizeMode::Fill, 800, 600);
+        assert_eq!(scaled_dimensions(source, &fill), (800, 600));
+        assert_eq!(scaled_dimensions(source, &fill.never_upscale(true)), (267, 200));
+    }
+
+    #[test]
+    fn crop_window_matches_the_target_aspect_ratio() {
+        assert_eq!(crop_window((400, 200), (100, 100)), (200, 200));
+        assert_eq!(crop_window((200, 400), (16, 9)), (200, 113));
+        assert_eq!(crop_window((300, 200), (3, 2)), (300, 200));
+    }
+
+    #[test]
+    fn fill_crops_the_center() {
+        // Red, green and blue thirds; a square crop keeps the green middle
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(60, 20, |x, _| image::Rgb([[255, 0, 0], [0, 255, 0], [0, 0, 255]][x as usize / 20])));
+        let resized = resize(img.clone(), &boxed(ResizeMode::Fill, 10, 10));
+        assert_eq!(resized.crop, Some(CropRect { x: 20, y: 0, width: 20, height: 20 }));
+        assert_eq!(resized.image.dimensions(), (10, 10));
+        assert!(resized.image.to_rgb8().pixels().all(|pixel| pixel.0 == [0, 255, 0]));
+        assert_eq!(resized.linear_light, Some(false));
+
+        // A box matching the source keeps the pixels untouched
+        let same = resize(img, &boxed(ResizeMode::Fit, 60, 20));
+        assert_eq!((same.crop, same.linear_light), (None, None));
+    }
+}
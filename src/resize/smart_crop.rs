@@ -0,0 +1,176 @@
+// smart_crop.rs
+use super::CropRect;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+
+/// Longest edge of the downsampled copy the windows are scored on.
+const ANALYSIS_EDGE: u32 = 256;
+
+const EDGE_WEIGHT: f32 = 1.0;
+const SATURATION_WEIGHT: f32 = 0.3;
+const SKIN_WEIGHT: f32 = 1.8;
+
+/// Average skin tone, as normalized RGB.
+const SKIN_COLOR: [f32; 3] = [0.78, 0.57, 0.44];
+/// Pixels closer than `1 - SKIN_THRESHOLD` to `SKIN_COLOR` (both normalized) count as skin.
+const SKIN_THRESHOLD: f32 = 0.8;
+/// Saturation below this counts as gray.
+const SATURATION_THRESHOLD: f32 = 0.4;
+
+/// Fraction of the mean score a window loses at the image edge, so ties go to the center.
+const CENTER_BIAS: f32 = 0.1;
+
+/// Picks the `width` x `height` window of `img` scoring highest on edge energy, saturation and skin tones.
+pub(super) fn best_window(img: &DynamicImage, width: u32, height: u32) -> CropRect {
+    let (source_w, source_h) = img.dimensions();
+    let (width, height) = (width.clamp(1, source_w.max(1)), height.clamp(1, source_h.max(1)));
+    if (width, height) == (source_w, source_h) {
+        return CropRect { x: 0, y: 0, width, height };
+    }
+
+    let scale = (ANALYSIS_EDGE as f32 / source_w.max(source_h) as f32).min(1.0);
+    let analysis_w = ((source_w as f32 * scale).round() as usize).max(1);
+    let analysis_h = ((source_h as f32 * scale).round() as usize).max(1);
+    let scores = score_map(img, analysis_w, analysis_h);
+    let table = summed_area_table(&scores, analysis_w, analysis_h);
+    let window_sum = |x: usize, y: usize, w: usize, h: usize| {
+        let stride = analysis_w + 1;
+        table[(y + h) * stride + x + w] - table[y * stride + x + w] - table[(y + h) * stride + x] + table[y * stride + x]
+    };
+
+    let window_w = ((width as f32 * scale).round() as usize).clamp(1, analysis_w);
+    let window_h = ((height as f32 * scale).round() as usize).clamp(1, analysis_h);
+    let (free_x, free_y) = (analysis_w - window_w, analysis_h - window_h);
+    let mean = window_sum(0, 0, analysis_w, analysis_h) / (analysis_w * analysis_h) as f64;
+
+    let mut best = (f64::MIN, 0, 0);
+    for y in 0..=free_y {
+        for x in 0..=free_x {
+            let density = window_sum(x, y, window_w, window_h) / (window_w * window_h) as f64;
+            // 0 when centered, 1 against an edge
+            let offset_x = if free_x == 0 { 0.0 } else { (2.0 * x as f64 / free_x as f64 - 1.0).abs() };
+            let offset_y = if free_y == 0 { 0.0 } else { (2.0 * y as f64 / free_y as f64 - 1.0).abs() };
+            let score = density - mean * CENTER_BIAS as f64 * offset_x.max(offset_y);
+            if score > best.0 {
+                best = (score, x, y);
+            }
+        }
+    }
+
+    // Back to source pixels, keeping the exact requested window size
+    let x = ((best.1 as f32 / scale).round() as u32).min(source_w - width);
+    let y = ((best.2 as f32 / scale).round() as u32).min(source_h - height);
+    CropRect { x, y, width, height }
+}
+
+/// Per-pixel interest of `img` downsampled to `width` x `height`, row-major.
+fn score_map(img: &DynamicImage, width: usize, height: usize) -> Vec<f32> {
+    let small = img.resize_exact(width as u32, height as u32, FilterType::Triangle).to_rgb8();
+    let pixels: Vec<[f32; 3]> = small.pixels().map(|pixel| pixel.0.map(|channel| channel as f32 / 255.0)).collect();
+    let luma: Vec<f32> = pixels.iter().map(|&[r, g, b]| 0.2126 * r + 0.7152 * g + 0.0722 * b).collect();
+    let skin_norm = SKIN_COLOR.iter().map(|channel| channel * channel).sum::<f32>().sqrt();
+
+    let mut scores = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let [r, g, b] = pixels[index];
+            let lightness = luma[index];
+
+            // Laplacian of luma, zero along the border
+            let edge = if x > 0 && y > 0 && x + 1 < width && y + 1 < height {
+                (4.0 * lightness - luma[index - 1] - luma[index + 1] - luma[index - width] - luma[index + width]).abs()
+            } else {
+                0.0
+            };
+
+            let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+            let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+            let saturation = if (0.05..=0.9).contains(&lightness) {
+                ((saturation - SATURATION_THRESHOLD) / (1.0 - SATURATION_THRESHOLD)).max(0.0)
+            } else {
+                0.0
+            };
+
+            let magnitude = (r * r + g * g + b * b).sqrt();
+            let skin = if magnitude > 0.0 && (0.2..=1.0).contains(&lightness) {
+                let distance = [r, g, b].iter().zip(SKIN_COLOR)
+                    .map(|(channel, skin)| (channel / magnitude - skin / skin_norm).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                ((1.0 - distance - SKIN_THRESHOLD) / (1.0 - SKIN_THRESHOLD)).max(0.0)
+            } else {
+                0.0
+            };
+
+            scores[index] = EDGE_WEIGHT * edge + SATURATION_WEIGHT * saturation + SKIN_WEIGHT * skin;
+        }
+    }
+    scores
+}
+
+/// Prefix sums with a zero first row and column, so any window sums in four lookups.
+fn summed_area_table(scores: &[f32], width: usize, height: usize) -> Vec<f64> {
+    let stride = width + 1;
+    let mut table = vec![0.0; stride * (height + 1)];
+    for y in 0..height {
+        let mut row_sum = 0.0;
+        for x in 0..width {
+            row_sum += scores[y * width + x] as f64;
+            table[(y + 1) * stride + x + 1] = table[y * stride + x + 1] + row_sum;
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// A flat gray 300x100 canvas with `patch` painted over x 230..270, y 30..70.
+    fn gray_with_patch(patch: impl Fn(u32, u32) -> [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(300, 100, |x, y| {
+            if (230..270).contains(&x) && (30..70).contains(&y) { Rgb(patch(x, y)) } else { Rgb([128, 128, 128]) }
+        }))
+    }
+
+    fn covers_patch(crop: CropRect) -> bool {
+        crop.x <= 230 && crop.x + crop.width >= 270 && crop.y <= 30 && crop.y + crop.height >= 70
+    }
+
+    #[test]
+    fn moves_toward_off_center_detail() {
+        let checkerboard = gray_with_patch(|x, y| if (x / 2 + y / 2) % 2 == 0 { [0, 0, 0] } else { [255, 255, 255] });
+        let crop = best_window(&checkerboard, 100, 100);
+        assert_eq!((crop.width, crop.height), (100, 100));
+        assert!(covers_patch(crop), "{}", crop);
+
+        let saturated = gray_with_patch(|_, _| [220, 30, 30]);
+        assert!(covers_patch(best_window(&saturated, 100, 100)));
+    }
+
+    #[test]
+    fn flat_images_stay_centered() {
+        let flat = DynamicImage::ImageRgb8(RgbImage::from_pixel(300, 100, Rgb([128, 128, 128])));
+        assert_eq!(best_window(&flat, 100, 100), CropRect { x: 100, y: 0, width: 100, height: 100 });
+    }
+
+    #[test]
+    fn returns_the_whole_image_when_it_already_fits() {
+        let img = gray_with_patch(|_, _| [0, 0, 0]);
+        let whole = CropRect { x: 0, y: 0, width: 300, height: 100 };
+        assert_eq!(best_window(&img, 300, 100), whole);
+        // Windows larger than the image are clamped to it
+        assert_eq!(best_window(&img, 500, 400), whole);
+    }
+
+    #[test]
+    fn sums_any_window_from_the_table() {
+        let scores: Vec<f32> = (1..=6).map(|value| value as f32).collect();
+        let table = summed_area_table(&scores, 3, 2);
+        // 1 2 3 / 4 5 6: everything, then the bottom-right 2x1
+        assert_eq!(table[2 * 4 + 3], 21.0);
+        assert_eq!(table[2 * 4 + 3] - table[4 + 3] - table[2 * 4 + 1] + table[4 + 1], 11.0);
+    }
+}
@@ -2,7 +2,6 @@ use std::time::{Instant, Duration};
 use std::sync::Arc;
 use std::sync::mpsc;
 use std::thread;
-use chrono;
 use parking_lot::Mutex;
 
 pub struct Logger {